@@ -0,0 +1,96 @@
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    engine::MigrationEngine,
+    errors::MigrationsError,
+    execute::{execute, QueryChunk},
+    history::{self, Migration},
+    plan::binding,
+    schema::{self, DatabaseSchema, SchemaItemKind},
+};
+
+impl<C: Connection> MigrationEngine<C> {
+    /// Adopts the engine for a database that was created without it. Creates the migrations table and records every
+    /// migration numbered up to and including `version` as applied, without running anything. Neither the schema
+    /// files nor any migration run, so the database must already contain the changes of those migrations. The next
+    /// [`MigrationEngine::run`] applies the migrations after `version` and the repeatable migrations.
+    ///
+    /// Fails with [`MigrationsError::MigrationsTableExists`] if the migrations table already exists. Returns the
+    /// recorded rows.
+    pub async fn baseline(
+        &self,
+        client: &Surreal<C>,
+        version: u32,
+    ) -> Result<Vec<Migration>, MigrationsError> {
+        self.locked(client, self.baseline_unlocked(client, version))
+            .await
+    }
+
+    async fn baseline_unlocked(
+        &self,
+        client: &Surreal<C>,
+        version: u32,
+    ) -> Result<Vec<Migration>, MigrationsError> {
+        if history::table_exists(client, &self.migrations_table).await? {
+            return Err(MigrationsError::MigrationsTableExists {
+                table: self.migrations_table.clone(),
+            });
+        }
+
+        let baselined = self.recorded_migrations(self.migration_files()?, version)?;
+
+        let mut queries = vec![
+            QueryChunk::new("BEGIN TRANSACTION;"),
+            QueryChunk::new(history::define_table_sql(&self.migrations_table)),
+        ];
+        queries.extend((0..baselined.len()).map(|index| self.insert_query(index)));
+        queries.push(QueryChunk::new("COMMIT TRANSACTION;"));
+        let bindings = baselined
+            .iter()
+            .enumerate()
+            .map(|(index, migration)| (binding(index), migration.clone()))
+            .collect();
+        execute(client, &queries, bindings).await?;
+
+        Ok(baselined)
+    }
+
+    /// Fails with [`MigrationsError::DatabaseNotEmpty`] if the current database already defines tables, functions,
+    /// params, analyzers or models, unless the validation policy allows it. Users and accesses are not counted, since
+    /// they are often set up before the first migration run.
+    pub(crate) async fn check_database_is_empty(
+        &self,
+        client: &Surreal<C>,
+    ) -> Result<(), MigrationsError> {
+        if self.validation.allow_non_empty_database {
+            return Ok(());
+        }
+        let db_info = schema::info(client, "INFO FOR DB;").await?;
+        let schema = DatabaseSchema::from_db_info(&db_info)?;
+        let internal_tables = self.internal_tables();
+        let found: Vec<String> = schema
+            .items()
+            .into_keys()
+            .filter(|item| match item.kind {
+                SchemaItemKind::Table => !internal_tables.contains(&item.name),
+                SchemaItemKind::Function
+                | SchemaItemKind::Param
+                | SchemaItemKind::Analyzer
+                | SchemaItemKind::Model => true,
+                SchemaItemKind::Field
+                | SchemaItemKind::Index
+                | SchemaItemKind::Event
+                | SchemaItemKind::Access
+                | SchemaItemKind::User => false,
+            })
+            .map(|item| item.to_string())
+            .collect();
+        if found.is_empty() {
+            return Ok(());
+        }
+        Err(MigrationsError::DatabaseNotEmpty {
+            table: self.migrations_table.clone(),
+            found: found.join(", "),
+        })
+    }
+}
@@ -0,0 +1,441 @@
+//! `surrealdb-migrate`, a command-line migrator that runs the engine against migration and schema directories on disk.
+
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use surrealdb::{
+    engine::any::{self, Any},
+    opt::auth::{Database, Namespace, Root},
+    Surreal,
+};
+use surrealdb_migration_engine::{
+    new_migration, squash, DirectorySource, GenerateTarget, LockPolicy, MigrationEngine,
+    MigrationTarget, NewMigrationOptions, PlanAction, RepairOperation, ValidationPolicy,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "surrealdb-migrate",
+    version,
+    about = "Runs SurrealDB migrations"
+)]
+struct Cli {
+    #[command(flatten)]
+    connection: ConnectionArgs,
+    #[command(flatten)]
+    engine: EngineArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct ConnectionArgs {
+    /// The database to connect to, e.g. `ws://localhost:8000`, `http://localhost:8000` or `mem://`.
+    #[arg(long, env = "SURREALDB_URL")]
+    url: Option<String>,
+    #[arg(long, env = "SURREALDB_NS")]
+    namespace: Option<String>,
+    #[arg(long, env = "SURREALDB_DB")]
+    database: Option<String>,
+    /// Signs in with this user. Without it, the connection is unauthenticated.
+    #[arg(long, env = "SURREALDB_USER")]
+    username: Option<String>,
+    #[arg(long, env = "SURREALDB_PASS", hide_env_values = true)]
+    password: Option<String>,
+    /// The level the user is defined on.
+    #[arg(long, env = "SURREALDB_AUTH_LEVEL", value_enum, default_value_t = AuthLevel::Root)]
+    auth_level: AuthLevel,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AuthLevel {
+    Root,
+    Namespace,
+    Database,
+}
+
+#[derive(Args)]
+struct EngineArgs {
+    /// The directory holding the migration files.
+    #[arg(long, env = "SURREALDB_MIGRATIONS_DIR", default_value = "migrations")]
+    migrations_dir: PathBuf,
+    /// The directory holding the schema files.
+    #[arg(long, env = "SURREALDB_SCHEMA_DIR", default_value = "schema")]
+    schema_dir: PathBuf,
+    /// The table that records which migrations have ran.
+    #[arg(long, env = "SURREALDB_MIGRATIONS_TABLE", default_value = surrealdb_migration_engine::DEFAULT_MIGRATIONS_TABLE)]
+    migrations_table: String,
+    /// The environment to run in, for migrations with an `@env` directive.
+    #[arg(long, env = "SURREALDB_MIGRATIONS_ENV")]
+    environment: Option<String>,
+    /// Take the migration lock, so concurrent migrators run one after another.
+    #[arg(long)]
+    lock: bool,
+    /// Apply the schema files even if the database has no migrations table but already defines tables, instead of
+    /// asking for a `baseline`.
+    #[arg(long)]
+    allow_non_empty: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Applies the pending migrations, or creates the schema if the migrations table does not exist.
+    Up {
+        /// Only apply the pending migrations numbered up to and including this version.
+        #[arg(long, conflicts_with = "step")]
+        to: Option<u32>,
+        /// Only apply this many of the pending migrations.
+        #[arg(long)]
+        step: Option<usize>,
+    },
+    /// Lists every migration as applied, pending or unknown.
+    Status,
+    /// Shows what `up` would do without changing the database.
+    Plan {
+        /// Also print the SurrealQL that would be sent.
+        #[arg(long)]
+        sql: bool,
+    },
+    /// Checks the files and the migrations table without changing the database.
+    Verify,
+    /// Rolls back applied migrations with their down files. Rolls back the newest one unless `--to` is given.
+    Rollback {
+        /// Roll back every migration numbered above this one.
+        #[arg(long)]
+        to: Option<u32>,
+    },
+    /// Adopts an existing database by creating the migrations table and recording the migrations up to `--version` as
+    /// applied, without running anything.
+    Baseline {
+        /// The number of the last migration the database already contains.
+        #[arg(long)]
+        version: u32,
+    },
+    /// Fixes the migrations table. Shows the changes and asks for confirmation before making them.
+    Repair {
+        #[command(subcommand)]
+        operation: RepairCommand,
+        /// Make the changes without asking for confirmation.
+        #[arg(long, global = true)]
+        yes: bool,
+    },
+    /// Creates the next numbered migration file in the migrations directory. Does not connect to the database.
+    New {
+        /// What the migration does, used for the file name, e.g. `add age to user`.
+        description: String,
+        /// Also create a down file.
+        #[arg(long)]
+        down: bool,
+        /// Start the file with a `@description` directive.
+        #[arg(long)]
+        header: bool,
+    },
+    /// Consolidates the schema files into one schema file and archives the migrations up to `--through`. Does not
+    /// connect to the database.
+    Squash {
+        /// The number of the last migration to squash.
+        #[arg(long)]
+        through: u32,
+    },
+    /// Creates the next numbered migration file with the statements that turn the database into what the schema
+    /// files define.
+    Generate {
+        /// What the migration does, used for the file name.
+        description: String,
+        /// Compare the schema files with what the migrations produce instead of with the database. Does not connect to
+        /// the database.
+        #[arg(long)]
+        against_migrations: bool,
+        /// Also create a down file that reverts the changes.
+        #[arg(long)]
+        down: bool,
+        /// Start the file with a `@description` directive.
+        #[arg(long)]
+        header: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum RepairCommand {
+    /// Sets the recorded checksums to the checksums of the current files.
+    Checksums,
+    /// Deletes the rows of failed migrations, so that they run again.
+    DeleteFailed,
+    /// Changes a recorded file name after the file was renamed.
+    Rename { from: String, to: String },
+    /// Changes a recorded number after the files were renumbered.
+    Renumber { from: u32, to: u32 },
+}
+
+impl From<RepairCommand> for RepairOperation {
+    fn from(command: RepairCommand) -> Self {
+        match command {
+            RepairCommand::Checksums => RepairOperation::RecomputeChecksums,
+            RepairCommand::DeleteFailed => RepairOperation::DeleteFailed,
+            RepairCommand::Rename { from, to } => RepairOperation::Rename { from, to },
+            RepairCommand::Renumber { from, to } => RepairOperation::Renumber { from, to },
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    // These commands only touch the files, so they do not need a connection.
+    match &cli.command {
+        Command::New {
+            description,
+            down,
+            header,
+        } => {
+            let created = new_migration(
+                &cli.engine.migrations_dir,
+                description,
+                NewMigrationOptions {
+                    down: *down,
+                    header: *header,
+                },
+            )?;
+            println!("Created {}", created.path.display());
+            if let Some(down_path) = created.down_path {
+                println!("Created {}", down_path.display());
+            }
+            return Ok(());
+        }
+        Command::Squash { through } => {
+            let squashed = squash(&cli.engine.migrations_dir, &cli.engine.schema_dir, *through)?;
+            for file_name in squashed.archived.iter() {
+                println!("Archived {file_name}");
+            }
+            println!("Created {}", squashed.schema_path.display());
+            return Ok(());
+        }
+        Command::Generate {
+            description,
+            against_migrations: true,
+            down,
+            header,
+        } => {
+            let engine = engine(&cli.engine);
+            let target = GenerateTarget::Migrations;
+            return generate(&engine, target, &cli.engine, description, *down, *header).await;
+        }
+        _ => {}
+    }
+
+    let client = connect(&cli.connection).await?;
+    let engine = engine(&cli.engine);
+    match cli.command {
+        Command::Up { to, step } => {
+            let target = match (to, step) {
+                (Some(version), _) => MigrationTarget::Version(version),
+                (None, Some(steps)) => MigrationTarget::Steps(steps),
+                (None, None) => MigrationTarget::Latest,
+            };
+            let plan = engine.migrate_to(&client, target).await?;
+            match plan.action {
+                PlanAction::UpToDate => println!("Database is up to date"),
+                PlanAction::CreateSchema => {
+                    for file in plan.files.iter() {
+                        println!("Applied schema file {}", file.file_name);
+                    }
+                    println!("Recorded {} existing migrations", plan.history.len());
+                }
+                PlanAction::ApplyMigrations => {
+                    for file in plan.files.iter() {
+                        println!("Applied {}", file.file_name);
+                    }
+                }
+            }
+        }
+        Command::Status => {
+            for migration in engine.status(&client).await? {
+                println!("{migration}");
+            }
+        }
+        Command::Plan { sql } => {
+            let plan = engine.plan(&client).await?;
+            match plan.action {
+                PlanAction::UpToDate => println!("Database is up to date"),
+                PlanAction::CreateSchema => println!("Would create the schema from:"),
+                PlanAction::ApplyMigrations => println!("Would apply:"),
+            }
+            for file in plan.files.iter() {
+                println!("  {}", file.file_name);
+            }
+            if sql && !plan.is_empty() {
+                println!("\n{}", plan.sql());
+            }
+        }
+        Command::Verify => {
+            engine.verify(&client).await?;
+            println!("OK");
+        }
+        Command::Rollback { to } => {
+            let rolled_back = match to {
+                Some(version) => engine.rollback_to(&client, version).await?,
+                None => engine.rollback_last(&client).await?.into_iter().collect(),
+            };
+            if rolled_back.is_empty() {
+                println!("Nothing to roll back");
+            }
+            for migration in rolled_back {
+                println!("Rolled back {}", migration.file_name);
+            }
+        }
+        Command::Baseline { version } => {
+            let baselined = engine.baseline(&client, version).await?;
+            for migration in baselined.iter() {
+                println!("Recorded {}", migration.file_name);
+            }
+            println!(
+                "Baselined {} migrations, the schema files were not run",
+                baselined.len()
+            );
+        }
+        Command::Repair { operation, yes } => {
+            let plan = engine.plan_repair(&client, operation.into()).await?;
+            if plan.is_empty() {
+                println!("Nothing to repair");
+                return Ok(());
+            }
+            for change in plan.changes.iter() {
+                println!("{change}");
+            }
+            if !yes && !confirm("Make these changes?")? {
+                println!("Nothing changed");
+                return Ok(());
+            }
+            engine.repair(&client, &plan).await?;
+            println!("Repaired {} rows", plan.changes.len());
+        }
+        Command::Generate {
+            description,
+            down,
+            header,
+            ..
+        } => {
+            let target = GenerateTarget::Database(&client);
+            generate(&engine, target, &cli.engine, &description, down, header).await?;
+        }
+        Command::New { .. } | Command::Squash { .. } => unreachable!("handled before connecting"),
+    }
+    Ok(())
+}
+
+/// Asks `question` on the terminal and returns true if the answer is yes.
+fn confirm(question: &str) -> Result<bool, Box<dyn Error>> {
+    print!("{question} [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+async fn generate(
+    engine: &MigrationEngine<Any>,
+    target: GenerateTarget<'_, Any>,
+    args: &EngineArgs,
+    description: &str,
+    down: bool,
+    header: bool,
+) -> Result<(), Box<dyn Error>> {
+    let created = engine
+        .generate_migration(
+            target,
+            &args.migrations_dir,
+            description,
+            NewMigrationOptions { down, header },
+        )
+        .await?;
+    let Some(created) = created else {
+        println!("No differences, no migration created");
+        return Ok(());
+    };
+    println!("Created {}", created.path.display());
+    if let Some(down_path) = created.down_path {
+        println!("Created {}", down_path.display());
+    }
+    Ok(())
+}
+
+async fn connect(args: &ConnectionArgs) -> Result<Surreal<Any>, Box<dyn Error>> {
+    let url = args
+        .url
+        .as_deref()
+        .ok_or("--url or SURREALDB_URL is required")?;
+    let namespace = args
+        .namespace
+        .as_deref()
+        .ok_or("--namespace or SURREALDB_NS is required")?;
+    let database = args
+        .database
+        .as_deref()
+        .ok_or("--database or SURREALDB_DB is required")?;
+    let client = any::connect(url).await?;
+    if let Some(username) = args.username.as_deref() {
+        let password = args.password.as_deref().unwrap_or_default();
+        match args.auth_level {
+            AuthLevel::Root => {
+                client.signin(Root { username, password }).await?;
+            }
+            AuthLevel::Namespace => {
+                client
+                    .signin(Namespace {
+                        namespace,
+                        username,
+                        password,
+                    })
+                    .await?;
+            }
+            AuthLevel::Database => {
+                client
+                    .signin(Database {
+                        namespace,
+                        database,
+                        username,
+                        password,
+                    })
+                    .await?;
+            }
+        }
+    }
+    client.use_ns(namespace).use_db(database).await?;
+    Ok(client)
+}
+
+fn engine(args: &EngineArgs) -> MigrationEngine<Any> {
+    let mut engine = MigrationEngine::new(
+        DirectorySource::new(&args.migrations_dir),
+        DirectorySource::new(&args.schema_dir),
+    )
+    .migrations_table(args.migrations_table.as_str());
+    if let Some(environment) = args.environment.as_deref() {
+        engine = engine.environment(environment);
+    }
+    if args.lock {
+        engine = engine.lock(LockPolicy::default());
+    }
+    if args.allow_non_empty {
+        engine = engine.validation(ValidationPolicy {
+            allow_non_empty_database: true,
+            ..ValidationPolicy::default()
+        });
+    }
+    engine
+}
@@ -0,0 +1,46 @@
+use sha2::{Digest, Sha256};
+
+use crate::sql;
+
+/// How the checksum of a migration file is computed, see [`crate::MigrationEngine::checksum_mode`].
+///
+/// Stored checksums record the mode they were computed with, so changing the mode does not invalidate migrations
+/// that already ran.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumMode {
+    /// Any change to the file changes the checksum.
+    #[default]
+    Exact,
+    /// Comments and whitespace between tokens do not affect the checksum.
+    IgnoreWhitespaceAndComments,
+}
+
+impl ChecksumMode {
+    fn prefix(self) -> &'static str {
+        match self {
+            ChecksumMode::Exact => "sha256",
+            ChecksumMode::IgnoreWhitespaceAndComments => "sha256-normalized",
+        }
+    }
+
+    /// The mode a stored checksum was computed with.
+    pub(crate) fn of(checksum: &str) -> Option<Self> {
+        let (prefix, _) = checksum.split_once(':')?;
+        [
+            ChecksumMode::Exact,
+            ChecksumMode::IgnoreWhitespaceAndComments,
+        ]
+        .into_iter()
+        .find(|mode| mode.prefix() == prefix)
+    }
+
+    pub(crate) fn checksum(self, sql: &str) -> String {
+        let digest = match self {
+            ChecksumMode::Exact => Sha256::digest(sql.as_bytes()),
+            ChecksumMode::IgnoreWhitespaceAndComments => {
+                Sha256::digest(sql::normalize(sql).as_bytes())
+            }
+        };
+        format!("{}:{:x}", self.prefix(), digest)
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::{
+    errors::MigrationsError,
+    sql::{self, Token},
+};
+
+/// Per-file settings read from the comments at the top of a migration file, e.g.
+/// ```text
+/// -- @description Backfill user ages
+/// -- @no_transaction
+/// -- @timeout 30s
+/// -- @env prod,staging
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Directives {
+    /// `@no_transaction`: run the migration outside of a transaction, whatever the transaction mode.
+    pub no_transaction: bool,
+    /// `@description <text>`.
+    pub description: Option<String>,
+    /// `@timeout <duration>`: fail if the migration does not finish in time, e.g. `500ms`, `30s`, `5m` or `1h`.
+    pub timeout: Option<Duration>,
+    /// `@env <name>,<name>`: only run the migration when the engine's environment is one of these.
+    /// `None` runs the migration in every environment.
+    pub env: Option<Vec<String>>,
+}
+
+impl Directives {
+    /// Returns true if a migration with these directives runs in `environment`.
+    pub fn runs_in(&self, environment: Option<&str>) -> bool {
+        match (&self.env, environment) {
+            (None, _) => true,
+            (Some(envs), Some(environment)) => envs.iter().any(|env| env == environment),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// Parses the directives in the leading comment block of `sql`. Unknown directives are ignored, while a known directive
+/// with an invalid value is an error.
+pub(crate) fn parse(file_name: &str, sql: &str) -> Result<Directives, MigrationsError> {
+    let mut directives = Directives::default();
+    for token in sql::tokenize(sql) {
+        let comment = match token {
+            Token::Code(text) if text.trim().is_empty() => continue,
+            Token::Comment(comment) => comment,
+            _ => break,
+        };
+        let comment = comment
+            .trim_start_matches("--")
+            .trim_start_matches("//")
+            .trim_start_matches('#')
+            .trim();
+        let Some(directive) = comment.strip_prefix('@') else {
+            continue;
+        };
+        let (name, value) = directive
+            .split_once(char::is_whitespace)
+            .map_or((directive, ""), |(name, value)| (name, value.trim()));
+        let invalid = || MigrationsError::InvalidDirective {
+            file_name: file_name.to_owned(),
+            directive: comment.to_owned(),
+        };
+        match name {
+            "no_transaction" if value.is_empty() => directives.no_transaction = true,
+            "description" if !value.is_empty() => directives.description = Some(value.to_owned()),
+            "timeout" => directives.timeout = Some(parse_duration(value).ok_or_else(invalid)?),
+            "env" if !value.is_empty() => {
+                directives.env = Some(
+                    value
+                        .split(',')
+                        .map(|env| env.trim().to_owned())
+                        .filter(|env| !env.is_empty())
+                        .collect(),
+                )
+            }
+            "no_transaction" | "description" | "env" => return Err(invalid()),
+            // Other `@` comments, e.g. `@author`, are left to the people reading the file.
+            _ => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    "Ignoring unknown directive '{}' in '{}'",
+                    comment,
+                    file_name
+                );
+            }
+        }
+    }
+    Ok(directives)
+}
+
+fn parse_duration(value: &str) -> Option<Duration> {
+    let duration_re = Regex::new(r"^(\d+)(ms|s|m|h)$").unwrap();
+    let captures = duration_re.captures(value)?;
+    let amount = captures.get(1)?.as_str().parse::<u64>().ok()?;
+    match captures.get(2)?.as_str() {
+        "ms" => Some(Duration::from_millis(amount)),
+        "s" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_secs(amount.checked_mul(60)?)),
+        "h" => Some(Duration::from_secs(amount.checked_mul(60 * 60)?)),
+        _ => None,
+    }
+}
@@ -0,0 +1,27 @@
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    engine::MigrationEngine,
+    errors::MigrationsError,
+    schema::{self, SchemaDifference},
+};
+
+impl<C: Connection> MigrationEngine<C> {
+    /// Compares the structure of the database `client` is connected to with the one the schema files produce, e.g. to
+    /// find manual hotfixes that never made it into a migration. The tables the engine manages itself are ignored.
+    ///
+    /// The expected structure is built in an in-memory database, so this is only available with the `kv-mem`
+    /// feature. Differences are reported with the schema files as expected and the live database as actual: a
+    /// [`crate::SchemaChange::Extra`] is only defined in the live database. Users and accesses only defined in the live
+    /// database are not reported, since they are usually created outside of the schema files.
+    pub async fn detect_drift(
+        &self,
+        client: &Surreal<C>,
+    ) -> Result<Vec<SchemaDifference>, MigrationsError> {
+        let from_schema = self.schema_database().await?;
+        let ignored_tables = self.internal_tables();
+        let expected = schema::read_without(&from_schema, &ignored_tables).await?;
+        let actual = schema::read_without(client, &ignored_tables).await?;
+        Ok(schema::without_extra_credentials(expected.diff(&actual)))
+    }
+}
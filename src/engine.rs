@@ -0,0 +1,418 @@
+use std::future::Future;
+
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    checksum::ChecksumMode,
+    errors::MigrationsError,
+    files::{get_sql_files, load_repeatable_files, load_sql_files, validate_numbering, SqlFile},
+    history::{self, HistoryStatus, Migration, REPEATABLE_NUMBER},
+    hooks::{Hook, HookContext, HookEvent},
+    lock::LockPolicy,
+    plan::{MigrationPlan, MigrationTarget},
+    rust_migration::{BoxError, RustMigration},
+    source::{EmbeddedSource, MigrationSource},
+    squash::{load_archive, ArchivedMigration},
+};
+
+/// The default name of the table that records which migrations have ran.
+pub const DEFAULT_MIGRATIONS_TABLE: &str = "migrations";
+
+/// Which checks are enforced on the migration and schema files and on the migrations history table.
+/// The default enforces all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValidationPolicy {
+    /// Allow gaps in file numbers, e.g. `0001`, `0002`, `0005`. Numbers must still be unique.
+    pub allow_gaps: bool,
+    /// Allow a migration file to have a different name than the one recorded in the history table for its number.
+    pub allow_renamed_files: bool,
+    /// Allow migrations recorded in the history table to no longer have a file.
+    pub allow_missing_files: bool,
+    /// Allow a migration file to change after it was recorded in the history table.
+    pub allow_changed_files: bool,
+    /// Allow the schema files to run on a database that has no migrations table but already defines tables, functions,
+    /// params, analyzers or models. Otherwise such a database needs [`MigrationEngine::baseline`].
+    pub allow_non_empty_database: bool,
+}
+
+/// How migrations are grouped into transactions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransactionMode {
+    /// Every pending migration and its history row run in one transaction, so if one fails, they all fail.
+    #[default]
+    Single,
+    /// Each migration runs in its own transaction together with its history row. Migrations before a failing one stay
+    /// applied, so a rerun continues from the failing migration.
+    PerMigration,
+    /// Migrations run without a transaction, e.g. for statements SurrealDB does not allow inside transactions. A
+    /// migration's history row is only inserted once all of its statements succeeded. A failing migration may be left
+    /// partially applied, so it is recorded as [`crate::HistoryStatus::Failed`] and later runs refuse to continue until
+    /// the row is removed with [`MigrationEngine::repair`].
+    None,
+}
+
+/// A configurable migration engine. The migration and schema files are read from [`MigrationSource`]s and `C` is the
+/// SurrealDB engine of the client, usually inferred.
+/// ```ignore
+/// MigrationEngine::embedded::<MigrationFiles, SchemaFiles>()
+///     .migrations_table("schema_history")
+///     .run(&client)
+///     .await?;
+/// ```
+pub struct MigrationEngine<C: Connection> {
+    pub(crate) migration_source: Box<dyn MigrationSource>,
+    pub(crate) schema_source: Box<dyn MigrationSource>,
+    pub(crate) migrations_table: String,
+    pub(crate) validation: ValidationPolicy,
+    pub(crate) checksum_mode: ChecksumMode,
+    pub(crate) transaction_mode: TransactionMode,
+    pub(crate) environment: Option<String>,
+    pub(crate) rust_migrations: Vec<RustMigration<C>>,
+    pub(crate) hooks: Vec<Hook<C>>,
+    pub(crate) lock: Option<LockPolicy>,
+    #[cfg(feature = "kv-mem")]
+    pub(crate) baseline_schema: Option<Box<dyn MigrationSource>>,
+}
+
+impl<C: Connection> MigrationEngine<C> {
+    /// Creates an engine with the default configuration that reads the migration and schema files from the given
+    /// sources, e.g. a [`crate::DirectorySource`] or a [`crate::MemorySource`].
+    pub fn new(
+        migration_source: impl MigrationSource + 'static,
+        schema_source: impl MigrationSource + 'static,
+    ) -> Self {
+        Self {
+            migration_source: Box::new(migration_source),
+            schema_source: Box::new(schema_source),
+            migrations_table: DEFAULT_MIGRATIONS_TABLE.to_owned(),
+            validation: ValidationPolicy::default(),
+            checksum_mode: ChecksumMode::default(),
+            transaction_mode: TransactionMode::default(),
+            environment: None,
+            rust_migrations: Vec::new(),
+            hooks: Vec::new(),
+            lock: None,
+            #[cfg(feature = "kv-mem")]
+            baseline_schema: None,
+        }
+    }
+
+    /// Creates an engine with the default configuration that reads the files embedded with `rust_embed`, the same one
+    /// used by [`crate::run`].
+    pub fn embedded<MigrationFiles, SchemaFiles>() -> Self
+    where
+        MigrationFiles: rust_embed::RustEmbed,
+        SchemaFiles: rust_embed::RustEmbed,
+    {
+        Self::new(
+            EmbeddedSource::<MigrationFiles>::new(),
+            EmbeddedSource::<SchemaFiles>::new(),
+        )
+    }
+
+    /// Sets the name of the table that records which migrations have ran. Defaults to `migrations`.
+    pub fn migrations_table(mut self, name: impl Into<String>) -> Self {
+        self.migrations_table = name.into();
+        self
+    }
+
+    /// Sets which checks are enforced on the files and the history table.
+    pub fn validation(mut self, policy: ValidationPolicy) -> Self {
+        self.validation = policy;
+        self
+    }
+
+    /// Sets how the checksums of newly recorded migrations are computed. Defaults to [`ChecksumMode::Exact`].
+    pub fn checksum_mode(mut self, mode: ChecksumMode) -> Self {
+        self.checksum_mode = mode;
+        self
+    }
+
+    /// Sets how migrations are grouped into transactions. Defaults to [`TransactionMode::Single`].
+    /// Schema creation always runs in a single transaction, unless the mode is [`TransactionMode::None`].
+    pub fn transaction_mode(mut self, mode: TransactionMode) -> Self {
+        self.transaction_mode = mode;
+        self
+    }
+
+    /// Sets the environment the engine runs in, e.g. `prod`. Migrations with an `@env` directive only run in the
+    /// environments they list. Without an environment, migrations with an `@env` directive never run.
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    /// Takes an advisory lock in the database around [`MigrationEngine::run`] and the rollbacks, so concurrent
+    /// processes migrate one after another. A process that waited for the lock plans its run only once it holds it,
+    /// so it sees the migrations the previous holder applied. Without a policy, no lock is taken.
+    pub fn lock(mut self, policy: LockPolicy) -> Self {
+        self.lock = Some(policy);
+        self
+    }
+
+    /// Sets the schema the first migration applies to, used by [`MigrationEngine::verify_schema_equivalence`].
+    /// Defaults to an empty database. After a [`crate::squash`], this is usually a copy of the squashed schema file.
+    #[cfg(feature = "kv-mem")]
+    pub fn baseline_schema(mut self, source: impl MigrationSource + 'static) -> Self {
+        self.baseline_schema = Some(Box::new(source));
+        self
+    }
+
+    /// Registers a migration written in Rust. It is ordered, validated and recorded together with the `.surql`
+    /// migration files, so `number` must fit in their sequence, and `name` is recorded as its file name, e.g.
+    /// `0003_rehash_passwords`.
+    ///
+    /// The migration receives a clone of the client. It cannot join a SurrealQL transaction, so it always runs on its
+    /// own and its history row is inserted once it succeeded.
+    /// ```ignore
+    /// engine.rust_migration(3, "0003_rehash_passwords", |db: Surreal<Client>| async move {
+    ///     let users: Vec<User> = db.select("user").await?;
+    ///     // ...
+    ///     Ok::<_, BoxError>(())
+    /// })
+    /// ```
+    pub fn rust_migration<F, Fut>(
+        mut self,
+        number: u32,
+        name: impl Into<String>,
+        migration: F,
+    ) -> Self
+    where
+        F: Fn(Surreal<C>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), BoxError>> + Send + 'static,
+    {
+        self.rust_migrations
+            .push(RustMigration::new(number, name.into(), migration));
+        self
+    }
+
+    /// Registers an async callback for `event`, e.g. to take a backup before the run or to emit an audit event after
+    /// each migration. Hooks receive the [`HookContext`] and a clone of the client, run in the order they were
+    /// registered and only fire when the run changes the database. A failing hook stops the run with
+    /// [`MigrationsError::HookFailed`], except for [`HookEvent::Failure`] hooks, whose errors are ignored so that the
+    /// original error is returned.
+    ///
+    /// Migrations that share a transaction are sent together, so their before-migration hooks all fire before the
+    /// transaction and their after-migration hooks after it commits.
+    /// ```ignore
+    /// engine.hook(HookEvent::AfterMigration, |context: HookContext, _db: Surreal<Client>| async move {
+    ///     println!("Applied {}", context.files[0].file_name);
+    ///     Ok::<_, BoxError>(())
+    /// })
+    /// ```
+    pub fn hook<F, Fut>(mut self, event: HookEvent, hook: F) -> Self
+    where
+        F: Fn(HookContext, Surreal<C>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), BoxError>> + Send + 'static,
+    {
+        self.hooks.push(Hook::new(event, hook));
+        self
+    }
+
+    /// If the migrations table does not exist, run only the schema files, create the migrations table and add all of the current migration files to the table.
+    /// If the migrations table does exist, run any migration files that are not in the migrations table and insert those migrations in the migrations table.
+    pub async fn run(&self, client: &Surreal<C>) -> Result<(), MigrationsError> {
+        self.migrate_to(client, MigrationTarget::Latest).await?;
+        Ok(())
+    }
+
+    /// Like [`MigrationEngine::run`], but only applies the pending migrations up to `target`, e.g.
+    /// `engine.migrate_to(&client, 5)` or `engine.migrate_to(&client, MigrationTarget::Steps(1))`. Repeatable
+    /// migrations only run once every versioned migration is applied, and `MigrationTarget::Steps(0)` runs nothing.
+    ///
+    /// If the migrations table does not exist, the schema files are applied as a single step. They contain every
+    /// migration, so a version older than the newest migration fails with [`MigrationsError::TargetBeforeSchema`].
+    ///
+    /// Returns the plan that was executed, computed while holding the lock. If the run fails, including while taking
+    /// the lock or planning, the [`HookEvent::Failure`] hooks are called.
+    pub async fn migrate_to(
+        &self,
+        client: &Surreal<C>,
+        target: impl Into<MigrationTarget>,
+    ) -> Result<MigrationPlan, MigrationsError> {
+        let target = target.into();
+        let mut files = Vec::new();
+        let result = self
+            .locked(client, async {
+                let plan = self.plan_to(client, target).await?;
+                files.clone_from(&plan.files);
+                self.execute_plan(client, &plan).await?;
+                Ok(plan)
+            })
+            .await;
+        if let Err(error) = &result {
+            // The error of the run is returned even if a failure hook fails too.
+            let _ = self
+                .call_hooks(client, HookEvent::Failure, &files, Some(error))
+                .await;
+        }
+        result
+    }
+
+    /// Validates the migration and schema files and, if the migrations table exists, checks that it agrees with the
+    /// migration files. Nothing is written to the database.
+    pub async fn verify(&self, client: &Surreal<C>) -> Result<(), MigrationsError> {
+        get_sql_files(self.schema_source.as_ref(), &self.validation).await?;
+        let file_migrations = self.migration_files()?;
+        if !history::table_exists(client, &self.migrations_table).await? {
+            return Ok(());
+        }
+        let db_migrations = history::select_all(client, &self.migrations_table).await?;
+        self.pending_migrations(&db_migrations, file_migrations)?;
+        Ok(())
+    }
+
+    /// Loads the migration files together with the Rust migrations, ordered by number, and validates their numbering.
+    pub(crate) fn migration_files(&self) -> Result<Vec<SqlFile>, MigrationsError> {
+        let mut migration_files = load_sql_files(self.migration_source.as_ref())?;
+        migration_files.extend(self.rust_migrations.iter().map(RustMigration::as_sql_file));
+        migration_files.sort_by(|a, b| a.number.cmp(&b.number));
+        // Squashed migrations keep their numbers, so the remaining files continue their sequence.
+        let archived = self.archived_migrations()?;
+        let mut numbering: Vec<(u32, &str)> = archived
+            .iter()
+            .map(|archived| (archived.number, archived.file_name.as_str()))
+            .chain(migration_files.iter().map(SqlFile::numbering))
+            .collect();
+        numbering.sort_by_key(|(number, _)| *number);
+        // Even with gaps allowed, 0 is recorded for repeatable migrations, see `REPEATABLE_NUMBER`.
+        if let Some((REPEATABLE_NUMBER, file_name)) = numbering.first() {
+            return Err(MigrationsError::FileNumbering {
+                file_name: file_name.to_string(),
+                expected: 1,
+                actual: REPEATABLE_NUMBER,
+            });
+        }
+        validate_numbering(numbering, &self.validation)?;
+        Ok(migration_files)
+    }
+
+    /// Loads the repeatable migrations, ordered by file name.
+    pub(crate) fn repeatable_files(&self) -> Result<Vec<SqlFile>, MigrationsError> {
+        load_repeatable_files(self.migration_source.as_ref())
+    }
+
+    /// The repeatable migrations that have not ran yet or changed since they last ran.
+    pub(crate) fn pending_repeatables(
+        &self,
+        db_migrations: &[Migration],
+    ) -> Result<Vec<SqlFile>, MigrationsError> {
+        let mut repeatables = self.repeatable_files()?;
+        repeatables.retain(|repeatable| {
+            let db_checksum = db_migrations
+                .iter()
+                .find(|db_migration| {
+                    db_migration.number == REPEATABLE_NUMBER
+                        && db_migration.file_name == repeatable.file_name
+                })
+                .and_then(|db_migration| db_migration.checksum.as_deref());
+            match db_checksum {
+                Some(db_checksum) => {
+                    let mode = ChecksumMode::of(db_checksum).unwrap_or(self.checksum_mode);
+                    repeatable.checksum(mode) != db_checksum
+                }
+                None => true,
+            }
+        });
+        Ok(repeatables)
+    }
+
+    /// The migrations that were squashed into the schema files, ordered by number.
+    pub(crate) fn archived_migrations(&self) -> Result<Vec<ArchivedMigration>, MigrationsError> {
+        load_archive(self.migration_source.as_ref())
+    }
+
+    /// The tables the engine manages itself, which are not part of the schema.
+    pub(crate) fn internal_tables(&self) -> Vec<String> {
+        vec![self.migrations_table.clone(), self.lock_table()]
+    }
+
+    /// Returns true if `migration_file` runs in the engine's environment.
+    pub(crate) fn runs_in_environment(&self, migration_file: &SqlFile) -> bool {
+        migration_file
+            .directives
+            .runs_in(self.environment.as_deref())
+    }
+
+    /// Checks the migrations recorded in the database against the migration files and returns the files that have
+    /// not ran yet.
+    pub(crate) fn pending_migrations(
+        &self,
+        db_migrations: &[Migration],
+        mut file_migrations: Vec<SqlFile>,
+    ) -> Result<Vec<SqlFile>, MigrationsError> {
+        let archived = self.archived_migrations()?;
+        // A database created before the squash recorded the migrations it ran, so every archived migration after the
+        // first recorded one must be among them, otherwise its changes are missing from the database.
+        let lowest_recorded = db_migrations
+            .iter()
+            .map(|db_migration| db_migration.number)
+            .filter(|number| *number != REPEATABLE_NUMBER)
+            .min();
+        if let Some(lowest_recorded) = lowest_recorded {
+            if let Some(archived) = archived.iter().find(|archived| {
+                archived.number > lowest_recorded
+                    && !db_migrations.iter().any(|db_migration| {
+                        db_migration.number == archived.number
+                            && db_migration.file_name == archived.file_name
+                    })
+            }) {
+                return Err(MigrationsError::ArchivedMigrationNotApplied {
+                    number: archived.number,
+                    file_name: archived.file_name.clone(),
+                });
+            }
+        }
+        for db_migration in db_migrations.iter() {
+            if db_migration.status == HistoryStatus::Failed {
+                return Err(MigrationsError::MigrationFailedPreviously {
+                    file_name: db_migration.file_name.clone(),
+                });
+            }
+            if db_migration.number == REPEATABLE_NUMBER {
+                continue; // See `pending_repeatables`
+            }
+            if archived.iter().any(|archived| {
+                archived.number == db_migration.number
+                    && archived.file_name == db_migration.file_name
+            }) {
+                continue;
+            }
+            let Some((index, migration_file)) = file_migrations
+                .iter()
+                .enumerate()
+                .find(|(_index, migration_file)| migration_file.number == db_migration.number)
+            else {
+                if self.validation.allow_missing_files {
+                    continue;
+                }
+                return Err(MigrationsError::MigrationFileInDbNotLongerExists {
+                    number: db_migration.number,
+                    db_file_name: db_migration.file_name.clone(),
+                });
+            };
+            if db_migration.file_name != migration_file.file_name
+                && !self.validation.allow_renamed_files
+            {
+                return Err(MigrationsError::MigrationFileDbMismatch {
+                    number: db_migration.number,
+                    file_name: migration_file.file_name.clone(),
+                    db_file_name: db_migration.file_name.clone(),
+                });
+            }
+            if let Some(db_checksum) = db_migration.checksum.as_deref() {
+                let mode = ChecksumMode::of(db_checksum).unwrap_or(self.checksum_mode);
+                let checksum = migration_file.checksum(mode);
+                if checksum != db_checksum && !self.validation.allow_changed_files {
+                    return Err(MigrationsError::MigrationFileChecksumMismatch {
+                        file_name: migration_file.file_name.clone(),
+                        expected: db_checksum.to_owned(),
+                        actual: checksum,
+                    });
+                }
+            }
+            file_migrations.remove(index);
+        }
+        Ok(file_migrations)
+    }
+}
@@ -0,0 +1,90 @@
+use surrealdb::{engine::any::Any, Connection, Surreal};
+
+use crate::{
+    engine::MigrationEngine,
+    errors::MigrationsError,
+    execute::{execute, QueryChunk},
+    files::{get_sql_files, MigrationKind, SqlFile},
+    schema::{self, SchemaDifference},
+};
+
+impl<C: Connection> MigrationEngine<C> {
+    /// Checks that the schema files produce the same database structure as the baseline schema followed by every
+    /// migration, see [`MigrationEngine::baseline_schema`]. A fresh database only gets the schema files and records
+    /// every migration as applied, so a migration whose change was not also made to the schema files would never
+    /// reach it.
+    ///
+    /// Both databases are built in memory and compared through `INFO FOR DB` and `INFO FOR TABLE`. The differences
+    /// are reported with the migrations as expected and the schema files as actual. Rust migrations and migrations
+    /// that do not run in the engine's environment are skipped.
+    ///
+    /// Only available with the `kv-mem` feature.
+    pub async fn verify_schema_equivalence(
+        &self,
+    ) -> Result<Vec<SchemaDifference>, MigrationsError> {
+        let from_migrations = self.migrated_database().await?;
+        let from_schema = self.schema_database().await?;
+        let ignored_tables = self.internal_tables();
+        let expected = schema::read_without(&from_migrations, &ignored_tables).await?;
+        let actual = schema::read_without(&from_schema, &ignored_tables).await?;
+        Ok(expected.diff(&actual))
+    }
+
+    /// An in-memory database created from the schema files and the repeatable migrations, like a fresh database.
+    pub(crate) async fn schema_database(&self) -> Result<Surreal<Any>, MigrationsError> {
+        let schemas = get_sql_files(self.schema_source.as_ref(), &self.validation).await?;
+        let repeatables = self.repeatable_files()?;
+        let repeatables = repeatables
+            .iter()
+            .filter(|repeatable| self.runs_in_environment(repeatable));
+        let client = in_memory_database().await?;
+        execute(
+            &client,
+            &file_chunks(schemas.iter().chain(repeatables)),
+            no_bindings(),
+        )
+        .await?;
+        Ok(client)
+    }
+
+    /// An in-memory database created from the baseline schema, the migrations and the repeatable migrations.
+    pub(crate) async fn migrated_database(&self) -> Result<Surreal<Any>, MigrationsError> {
+        let baseline = match &self.baseline_schema {
+            Some(source) => get_sql_files(source.as_ref(), &self.validation).await?,
+            None => Vec::new(),
+        };
+        let migrations = self.migration_files()?;
+        let repeatables = self.repeatable_files()?;
+        let migrations = migrations
+            .iter()
+            .chain(repeatables.iter())
+            .filter(|migration| {
+                migration.kind != MigrationKind::Rust && self.runs_in_environment(migration)
+            });
+        let client = in_memory_database().await?;
+        execute(
+            &client,
+            &file_chunks(baseline.iter().chain(migrations)),
+            no_bindings(),
+        )
+        .await?;
+        Ok(client)
+    }
+}
+
+/// A fresh database on the `kv-mem` engine.
+async fn in_memory_database() -> Result<Surreal<Any>, MigrationsError> {
+    let client = surrealdb::engine::any::connect("mem://").await?;
+    client.use_ns("migrations").use_db("migrations").await?;
+    Ok(client)
+}
+
+fn file_chunks<'a>(files: impl Iterator<Item = &'a SqlFile>) -> Vec<QueryChunk> {
+    files
+        .map(|file| QueryChunk::from_file(file.file_name.as_str(), file.sql.as_str()))
+        .collect()
+}
+
+fn no_bindings() -> Vec<(String, ())> {
+    Vec::new()
+}
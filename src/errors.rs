@@ -1,19 +1,171 @@
 use error_set::error_set;
 
 error_set! {
-    /// Errors related to migrations and schema creation. If more detail is needed, enable the "tracing" feature on the crate.
+    /// Errors related to migrations and schema creation.
     MigrationsError = {
-        CannotLoadFile,
-        /// Files are not numbered sequentially starteding from 1.
-        FileNumbering,
+        #[display("Cannot load file '{}'", file_name)]
+        CannotLoadFile {
+            file_name: String,
+        },
+        #[display("Cannot read file '{}': {}", file_name, error)]
+        CannotReadFile {
+            file_name: String,
+            error: std::io::Error,
+        },
+        #[display("Cannot read directory '{}': {}", path, error)]
+        CannotReadDirectory {
+            path: String,
+            error: std::io::Error,
+        },
+        #[display("Cannot write file '{}': {}", file_name, error)]
+        CannotWriteFile {
+            file_name: String,
+            error: std::io::Error,
+        },
+        /// A description for a new migration does not contain any letters or digits to name the file after.
+        #[display("Cannot name a migration file after '{}'", description)]
+        InvalidMigrationDescription {
+            description: String,
+        },
+        /// Files are not numbered sequentially starting from 1.
+        #[display("File '{}' is numbered {} but {} was expected", file_name, actual, expected)]
+        FileNumbering {
+            file_name: String,
+            expected: u32,
+            actual: u32,
+        },
+        /// Two files have the same number.
+        #[display("Files '{}' and '{}' are both numbered {}", first_file_name, second_file_name, number)]
+        DuplicateFileNumber {
+            number: u32,
+            first_file_name: String,
+            second_file_name: String,
+        },
         /// A file name does not follow the naming conventions outlined in the documentation.
-        FileNameMalformed,
-        MigrationFileDbMismatch,
-        MigrationFileInDbNotLongerExists,
-        InfoForDbTablesNotAnObject,
-        InfoForDbDoesNotContainTables,
-        InfoForDbNotAnObject,
-        InfoForDbHasNoData,
+        #[display("File name '{}' does not start with a number", file_name)]
+        FileNameMalformed {
+            file_name: String,
+        },
+        /// A known directive at the top of a file has an invalid value.
+        #[display("Invalid directive '{}' in '{}'", directive, file_name)]
+        InvalidDirective {
+            file_name: String,
+            directive: String,
+        },
+        /// The migration file for a number recorded in the migrations table has a different name.
+        #[display("Migration {} is named '{}' but was recorded in the database as '{}'", number, file_name, db_file_name)]
+        MigrationFileDbMismatch {
+            number: u32,
+            file_name: String,
+            db_file_name: String,
+        },
+        /// A migration recorded in the migrations table no longer has a file.
+        #[display("Migration {} '{}' is recorded in the database but its file no longer exists", number, db_file_name)]
+        MigrationFileInDbNotLongerExists {
+            number: u32,
+            db_file_name: String,
+        },
+        /// A migration file changed after it was recorded in the migrations table.
+        #[display("Migration file '{}' changed after it was recorded in the database. Recorded checksum '{}', current checksum '{}'", file_name, expected, actual)]
+        MigrationFileChecksumMismatch {
+            file_name: String,
+            expected: String,
+            actual: String,
+        },
+        /// A statement of a migration or schema file failed. `statement` and `line` are 1-based.
+        #[display("Statement {} on line {} of '{}' failed: {}", statement, line, file_name, error)]
+        MigrationStatementFailed {
+            file_name: String,
+            statement: usize,
+            line: usize,
+            error: surrealdb::Error,
+        },
+        /// A Rust migration returned an error.
+        #[display("Rust migration '{}' failed: {}", file_name, error)]
+        RustMigrationFailed {
+            file_name: String,
+            error: Box<dyn std::error::Error + Send + Sync>,
+        },
+        /// A migration did not finish within its `@timeout`. For remote engines the query may still be running on the server.
+        #[display("Migration '{}' did not finish within {:?}", file_name, timeout)]
+        MigrationTimedOut {
+            file_name: String,
+            timeout: std::time::Duration,
+        },
+        /// A hook registered with `MigrationEngine::hook` returned an error.
+        #[display("The {} hook failed: {}", event, error)]
+        HookFailed {
+            event: crate::HookEvent,
+            error: Box<dyn std::error::Error + Send + Sync>,
+        },
+        /// A down file does not have a migration file with the same number.
+        #[display("Down file '{}' does not have a migration file with the same number", file_name)]
+        DownFileWithoutMigration {
+            file_name: String,
+        },
+        /// A migration that needs to be rolled back does not have a down file.
+        #[display("Migration file '{}' cannot be rolled back because it does not have a down file", file_name)]
+        MissingDownMigration {
+            file_name: String,
+        },
+        /// A migration was squashed into the schema files before it ran on a database that already recorded earlier
+        /// migrations, so its changes are missing from the database.
+        #[display("Migration {} '{}' was squashed into the schema but never ran on this database. Apply it manually, then record it", number, file_name)]
+        ArchivedMigrationNotApplied {
+            number: u32,
+            file_name: String,
+        },
+        /// A migration that ran outside of a transaction is recorded as failed and may be partially applied.
+        #[display("Migration '{}' failed in a previous run and may be partially applied. Fix the database, then remove the failed row with `repair`", file_name)]
+        MigrationFailedPreviously {
+            file_name: String,
+        },
+        /// The migrations table does not exist and the schema files already contain a migration after the target.
+        #[display("Cannot create the schema at version {}: the schema files already contain '{}'", target, file_name)]
+        TargetBeforeSchema {
+            target: u32,
+            file_name: String,
+        },
+        /// Another process held the migration lock for longer than the lock policy waits.
+        #[display("The migration lock is held by '{}' and was not released within {:?}", owner, wait)]
+        MigrationLocked {
+            owner: String,
+            wait: std::time::Duration,
+        },
+        /// No migration is numbered at or below the number to squash through.
+        #[display("There are no migrations to squash through {}", through)]
+        NothingToSquash {
+            through: u32,
+        },
+        /// A migration with an `@env` directive cannot be folded into the schema, which runs in every environment.
+        #[display("Migration '{}' only runs in some environments and cannot be squashed into the schema", file_name)]
+        CannotSquashEnvMigration {
+            file_name: String,
+        },
+        /// An `INFO` statement returned something that does not look like a database structure.
+        #[display("`{}` returned an unexpected result: {}", statement, reason)]
+        UnexpectedInfo {
+            statement: String,
+            reason: String,
+        },
+        /// A repair would give a row of the migrations table the file name or number of another recorded row.
+        #[display("Cannot repair the migrations table: migration {} '{}' is already recorded with that file name or number", number, file_name)]
+        RepairConflict {
+            number: u32,
+            file_name: String,
+        },
+        /// The migrations table does not exist but the database already has definitions, so creating the schema would
+        /// apply it on top of them. Baseline the database or allow non-empty databases in the validation policy.
+        #[display("The database has no '{}' table but already defines {}. Baseline it or allow non-empty databases to apply the schema anyway", table, found)]
+        DatabaseNotEmpty {
+            table: String,
+            found: String,
+        },
+        /// The migrations table already exists, so the database cannot be baselined.
+        #[display("Cannot baseline a database that already has a '{}' table", table)]
+        MigrationsTableExists {
+            table: String,
+        },
         Surrealdb(surrealdb::Error),
     };
-}
\ No newline at end of file
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use surrealdb::{
+    error::{Api, Db},
+    Connection, Surreal,
+};
+
+use crate::{errors::MigrationsError, sql};
+
+/// SurrealQL sent as its own statement group, and the file it came from.
+#[derive(Debug, Clone)]
+pub(crate) struct QueryChunk {
+    pub(crate) sql: String,
+    pub(crate) file_name: Option<String>,
+}
+
+impl QueryChunk {
+    pub(crate) fn new(sql: impl Into<String>) -> Self {
+        Self {
+            sql: sql.into(),
+            file_name: None,
+        }
+    }
+
+    pub(crate) fn from_file(file_name: impl Into<String>, sql: impl Into<String>) -> Self {
+        Self {
+            sql: sql.into(),
+            file_name: Some(file_name.into()),
+        }
+    }
+}
+
+/// Sends `chunks` as a single query. If a statement fails, the error names the file and statement that caused it.
+pub(crate) async fn execute<T: Serialize + 'static>(
+    client: &Surreal<impl Connection>,
+    chunks: &[QueryChunk],
+    bindings: Vec<(String, T)>,
+) -> Result<(), MigrationsError> {
+    let Some((first, rest)) = chunks.split_first() else {
+        return Ok(());
+    };
+
+    let mut query = client.query(first.sql.as_str());
+    for chunk in rest {
+        query = query.query(chunk.sql.as_str());
+    }
+    for binding in bindings {
+        query = query.bind(binding);
+    }
+
+    let errors = query.await?.take_errors();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(statement_error(chunks, errors))
+}
+
+/// Finds the statement that caused a query to fail. When a statement inside a transaction fails, every other
+/// statement of the transaction also reports an error saying it was not executed, so those are skipped.
+fn statement_error(
+    chunks: &[QueryChunk],
+    mut errors: HashMap<usize, surrealdb::Error>,
+) -> MigrationsError {
+    let mut indexes: Vec<usize> = errors.keys().copied().collect();
+    indexes.sort_unstable();
+    let Some(index) = indexes
+        .iter()
+        .copied()
+        .find(|index| !is_not_executed(&errors[index]))
+    else {
+        let error = errors
+            .remove(&indexes[0])
+            .expect("index is a key of errors");
+        return MigrationsError::Surrealdb(error);
+    };
+    let error = errors.remove(&index).expect("index is a key of errors");
+
+    let mut remaining = index;
+    for chunk in chunks {
+        let statements = sql::statements(&chunk.sql);
+        if remaining < statements.len() {
+            return match &chunk.file_name {
+                Some(file_name) => MigrationsError::MigrationStatementFailed {
+                    file_name: file_name.clone(),
+                    statement: remaining + 1,
+                    line: statements[remaining].line,
+                    error,
+                },
+                None => MigrationsError::Surrealdb(error),
+            };
+        }
+        remaining -= statements.len();
+    }
+    MigrationsError::Surrealdb(error)
+}
+
+/// Returns true if `error` only reports that the statement was not executed because another statement of its
+/// transaction failed.
+fn is_not_executed(error: &surrealdb::Error) -> bool {
+    match error {
+        surrealdb::Error::Db(Db::QueryNotExecuted | Db::QueryNotExecutedDetail { .. }) => true,
+        // Remote engines only receive the message, so it is compared with the message of the same error.
+        surrealdb::Error::Api(Api::Query(message)) => {
+            let detail = Db::QueryNotExecutedDetail {
+                message: String::new(),
+            }
+            .to_string();
+            *message == Db::QueryNotExecuted.to_string() || message.starts_with(detail.trim_end())
+        }
+        _ => false,
+    }
+}
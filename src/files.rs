@@ -0,0 +1,222 @@
+use regex::Regex;
+
+use crate::{
+    checksum::ChecksumMode,
+    directives::{self, Directives},
+    engine::ValidationPolicy,
+    errors::MigrationsError,
+    history::REPEATABLE_NUMBER,
+    source::MigrationSource,
+    squash::ARCHIVE_FILE_NAME,
+};
+
+/// A migration or schema file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlFile {
+    pub file_name: String,
+    /// The number the file name starts with.
+    pub number: u32,
+    pub sql: String,
+    /// The directives in the comments at the top of the file.
+    pub directives: Directives,
+    pub kind: MigrationKind,
+    /// The paired down file, e.g. `0003_add_age.down.surql` for `0003_add_age.surql`.
+    pub down: Option<DownFile>,
+}
+
+/// Whether a migration is a `.surql` file or Rust code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationKind {
+    Surql,
+    /// A `.surql` file in the `repeatable/` directory or starting with `R_`, e.g. `R_functions.surql`. It is not
+    /// numbered, so the `number` of the [`SqlFile`] is [`crate::REPEATABLE_NUMBER`], and it runs again after the
+    /// versioned migrations whenever its checksum changes.
+    Repeatable,
+    /// Registered with [`crate::MigrationEngine::rust_migration`]. The `sql` of the [`SqlFile`] is empty.
+    Rust,
+}
+
+impl SqlFile {
+    /// The checksum of the file contents, as stored in the migrations table.
+    pub fn checksum(&self, mode: ChecksumMode) -> String {
+        mode.checksum(&self.sql)
+    }
+
+    /// The number and name, as checked by [`validate_numbering`].
+    pub(crate) fn numbering(&self) -> (u32, &str) {
+        (self.number, self.file_name.as_str())
+    }
+
+    /// The checksum stored in the migrations table. Rust migrations have no checksum.
+    pub(crate) fn recorded_checksum(&self, mode: ChecksumMode) -> Option<String> {
+        match self.kind {
+            MigrationKind::Surql | MigrationKind::Repeatable => Some(self.checksum(mode)),
+            MigrationKind::Rust => None,
+        }
+    }
+}
+
+/// A file that undoes a migration, see [`SqlFile::down`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownFile {
+    pub file_name: String,
+    pub sql: String,
+}
+
+/// Returns true if `file_name` is a repeatable migration, see [`MigrationKind::Repeatable`].
+pub(crate) fn is_repeatable(file_name: &str) -> bool {
+    file_name.starts_with("repeatable/")
+        || file_name
+            .rsplit('/')
+            .next()
+            .is_some_and(|base_name| base_name.starts_with("R_"))
+}
+
+/// Returns true if `file_name` is a down file, e.g. `0003_add_age.down.surql`.
+fn is_down_file(file_name: &str) -> bool {
+    std::path::Path::new(file_name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| stem.ends_with(".down"))
+}
+
+/// Loads and validates the files of `source`, ordered by number.
+pub(crate) async fn get_sql_files(
+    source: &dyn MigrationSource,
+    policy: &ValidationPolicy,
+) -> Result<Vec<SqlFile>, MigrationsError> {
+    let sql_files = load_sql_files(source)?;
+    validate_numbering(sql_files.iter().map(SqlFile::numbering), policy)?;
+    Ok(sql_files)
+}
+
+/// Loads the files of `source`, ordered by number, and pairs each with its down file. The numbering is not
+/// validated.
+pub(crate) fn load_sql_files(
+    source: &dyn MigrationSource,
+) -> Result<Vec<SqlFile>, MigrationsError> {
+    let (down_file_names, up_file_names): (Vec<String>, Vec<String>) = source
+        .file_names()?
+        .into_iter()
+        .filter(|file_name| file_name != ARCHIVE_FILE_NAME && !is_repeatable(file_name))
+        .partition(|file_name| is_down_file(file_name));
+
+    let mut sql_files: Vec<SqlFile> = up_file_names
+        .into_iter()
+        .map(|file_name| {
+            let sql = source.load(&file_name)?;
+            Ok(SqlFile {
+                number: file_number(&file_name)?,
+                directives: directives::parse(&file_name, &sql)?,
+                sql,
+                file_name,
+                kind: MigrationKind::Surql,
+                down: None,
+            })
+        })
+        .collect::<Result<Vec<_>, MigrationsError>>()?;
+
+    sql_files.sort_by(|a, b| a.number.cmp(&b.number));
+
+    for down_file_name in down_file_names {
+        let down_number = file_number(&down_file_name)?;
+        let sql_file = sql_files
+            .iter_mut()
+            .find(|sql_file| sql_file.number == down_number)
+            .ok_or_else(|| MigrationsError::DownFileWithoutMigration {
+                file_name: down_file_name.clone(),
+            })?;
+        if let Some(existing) = &sql_file.down {
+            return Err(MigrationsError::DuplicateFileNumber {
+                number: down_number,
+                first_file_name: existing.file_name.clone(),
+                second_file_name: down_file_name,
+            });
+        }
+        sql_file.down = Some(DownFile {
+            sql: source.load(&down_file_name)?,
+            file_name: down_file_name,
+        });
+    }
+
+    Ok(sql_files)
+}
+
+/// Loads the repeatable migrations of `source`, ordered by file name.
+pub(crate) fn load_repeatable_files(
+    source: &dyn MigrationSource,
+) -> Result<Vec<SqlFile>, MigrationsError> {
+    let mut file_names: Vec<String> = source
+        .file_names()?
+        .into_iter()
+        .filter(|file_name| is_repeatable(file_name))
+        .collect();
+    file_names.sort();
+    file_names
+        .into_iter()
+        .map(|file_name| {
+            if is_down_file(&file_name) {
+                return Err(MigrationsError::DownFileWithoutMigration { file_name });
+            }
+            let sql = source.load(&file_name)?;
+            Ok(SqlFile {
+                number: REPEATABLE_NUMBER,
+                directives: directives::parse(&file_name, &sql)?,
+                sql,
+                file_name,
+                kind: MigrationKind::Repeatable,
+                down: None,
+            })
+        })
+        .collect()
+}
+
+/// Checks that files, given as number and name and ordered by number, are numbered sequentially starting from 1, or
+/// only that the numbers are unique if the policy allows gaps.
+pub(crate) fn validate_numbering<'a>(
+    files: impl IntoIterator<Item = (u32, &'a str)>,
+    policy: &ValidationPolicy,
+) -> Result<(), MigrationsError> {
+    let files: Vec<(u32, &str)> = files.into_iter().collect();
+    if !policy.allow_gaps {
+        if let Some((number, file_name)) = files.first() {
+            if *number != 1 {
+                return Err(MigrationsError::FileNumbering {
+                    file_name: file_name.to_string(),
+                    expected: 1,
+                    actual: *number,
+                });
+            }
+        }
+    }
+    for ((a_number, a_file_name), (b_number, b_file_name)) in files.iter().zip(files.iter().skip(1))
+    {
+        if a_number == b_number {
+            return Err(MigrationsError::DuplicateFileNumber {
+                number: *a_number,
+                first_file_name: a_file_name.to_string(),
+                second_file_name: b_file_name.to_string(),
+            });
+        }
+        if !policy.allow_gaps && a_number + 1 != *b_number {
+            return Err(MigrationsError::FileNumbering {
+                file_name: b_file_name.to_string(),
+                expected: a_number + 1,
+                actual: *b_number,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Parses the number the last segment of `file_name` starts with, so files can be grouped in subdirectories.
+pub(crate) fn file_number(file_name: &str) -> Result<u32, MigrationsError> {
+    let number_re = Regex::new(r"^\d+").unwrap();
+    let base_name = file_name.rsplit('/').next().unwrap_or(file_name);
+    number_re
+        .captures(base_name)
+        .and_then(|captures| captures.get(0)?.as_str().parse::<u32>().ok())
+        .ok_or_else(|| MigrationsError::FileNameMalformed {
+            file_name: file_name.to_owned(),
+        })
+}
@@ -0,0 +1,194 @@
+use std::path::Path;
+
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    engine::MigrationEngine,
+    errors::MigrationsError,
+    history::escape_ident,
+    scaffold::{create_migration, NewMigration, NewMigrationOptions},
+    schema::{self, SchemaChange, SchemaDifference, SchemaItem, SchemaItemKind},
+    sql,
+};
+
+/// What [`MigrationEngine::generate_migration`] compares the schema files with.
+pub enum GenerateTarget<'a, C: Connection> {
+    /// The database `client` is connected to.
+    Database(&'a Surreal<C>),
+    /// The structure the baseline schema and the migrations produce, see [`MigrationEngine::baseline_schema`].
+    Migrations,
+}
+
+impl<C: Connection> MigrationEngine<C> {
+    /// The SurrealQL that turns `target` into the structure the schema files produce: `DEFINE ... OVERWRITE` for
+    /// missing and altered definitions and `REMOVE ...` for extra ones. Empty if there are no differences. Users and
+    /// accesses only defined in a target database are not removed.
+    ///
+    /// The schema files are applied to an in-memory database, so this is only available with the `kv-mem`
+    /// feature.
+    pub async fn migration_sql(
+        &self,
+        target: GenerateTarget<'_, C>,
+    ) -> Result<String, MigrationsError> {
+        let differences = self.target_differences(target).await?;
+        Ok(statements(&differences, Direction::Up))
+    }
+
+    /// Writes the statements of [`MigrationEngine::migration_sql`] to the next numbered migration file in
+    /// `directory`, see [`crate::new_migration`]. A down file generated with `options.down` reverts the definitions
+    /// to the ones of `target`. Returns `None` without creating a file if there are no differences.
+    pub async fn generate_migration(
+        &self,
+        target: GenerateTarget<'_, C>,
+        directory: impl AsRef<Path>,
+        description: &str,
+        options: NewMigrationOptions,
+    ) -> Result<Option<NewMigration>, MigrationsError> {
+        let differences = self.target_differences(target).await?;
+        if differences.is_empty() {
+            return Ok(None);
+        }
+        create_migration(
+            directory.as_ref(),
+            description,
+            options,
+            &statements(&differences, Direction::Up),
+            &statements(&differences, Direction::Down),
+        )
+        .map(Some)
+    }
+
+    /// The differences between the schema files, as expected, and `target`, as actual.
+    async fn target_differences(
+        &self,
+        target: GenerateTarget<'_, C>,
+    ) -> Result<Vec<SchemaDifference>, MigrationsError> {
+        let ignored_tables = self.internal_tables();
+        let from_schema = self.schema_database().await?;
+        let expected = schema::read_without(&from_schema, &ignored_tables).await?;
+        match target {
+            GenerateTarget::Database(client) => {
+                let actual = schema::read_without(client, &ignored_tables).await?;
+                // Removing the users and accesses of a live database could lock the application out.
+                Ok(schema::without_extra_credentials(expected.diff(&actual)))
+            }
+            GenerateTarget::Migrations => {
+                let from_migrations = self.migrated_database().await?;
+                let actual = schema::read_without(&from_migrations, &ignored_tables).await?;
+                Ok(expected.diff(&actual))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// From the actual structure to the expected one.
+    Up,
+    /// From the expected structure back to the actual one.
+    Down,
+}
+
+/// The statements that apply `differences` in `direction`. Definitions are ordered so that what a definition may
+/// depend on, e.g. the analyzer of an index or the table of a field, is defined before it and removed after it.
+fn statements(differences: &[SchemaDifference], direction: Direction) -> String {
+    let mut defines: Vec<(&SchemaItem, String)> = Vec::new();
+    let mut removes: Vec<&SchemaItem> = Vec::new();
+    for difference in differences {
+        let (from, to) = match (&difference.change, direction) {
+            (SchemaChange::Missing { expected }, Direction::Up) => (None, Some(expected)),
+            (SchemaChange::Missing { expected }, Direction::Down) => (Some(expected), None),
+            (SchemaChange::Extra { actual }, Direction::Up) => (Some(actual), None),
+            (SchemaChange::Extra { actual }, Direction::Down) => (None, Some(actual)),
+            (SchemaChange::Altered { expected, actual }, Direction::Up) => {
+                (Some(actual), Some(expected))
+            }
+            (SchemaChange::Altered { expected, actual }, Direction::Down) => {
+                (Some(expected), Some(actual))
+            }
+        };
+        match (from, to) {
+            (_, Some(definition)) => {
+                defines.push((&difference.item, sql::idempotent(definition)));
+            }
+            (Some(_), None) => removes.push(&difference.item),
+            (None, None) => {}
+        }
+    }
+    // Removing a table removes its fields, indexes and events.
+    let removed_tables: Vec<&str> = removes
+        .iter()
+        .filter(|item| item.kind == SchemaItemKind::Table)
+        .map(|item| item.name.as_str())
+        .collect();
+    removes.retain(|item| {
+        !item
+            .table
+            .as_deref()
+            .is_some_and(|table| removed_tables.contains(&table))
+    });
+
+    defines.sort_by_key(|(item, _)| (define_order(item.kind), (*item).clone()));
+    removes.sort_by_key(|item| (std::cmp::Reverse(define_order(item.kind)), (*item).clone()));
+
+    let mut migration = String::new();
+    for item in removes {
+        migration.push_str(&remove_sql(item));
+        migration.push('\n');
+    }
+    for (_, definition) in defines {
+        migration.push_str(definition.trim_end_matches(';'));
+        migration.push_str(";\n");
+    }
+    migration
+}
+
+/// The position of `kind` when defining; removing happens in reverse.
+fn define_order(kind: SchemaItemKind) -> u8 {
+    match kind {
+        SchemaItemKind::Analyzer => 0,
+        SchemaItemKind::Param => 1,
+        SchemaItemKind::Function => 2,
+        SchemaItemKind::Model => 3,
+        SchemaItemKind::Access => 4,
+        SchemaItemKind::User => 5,
+        SchemaItemKind::Table => 6,
+        SchemaItemKind::Field => 7,
+        SchemaItemKind::Index => 8,
+        SchemaItemKind::Event => 9,
+    }
+}
+
+/// The statement that removes `item`. Field, function, param and model names are used as reported by `INFO`, since
+/// they can hold paths or versions that must not be escaped.
+fn remove_sql(item: &SchemaItem) -> String {
+    let on_table = || {
+        format!(
+            " ON TABLE {}",
+            escape_ident(item.table.as_deref().unwrap_or_default())
+        )
+    };
+    match item.kind {
+        SchemaItemKind::Table => format!("REMOVE TABLE {};", escape_ident(&item.name)),
+        SchemaItemKind::Field => format!("REMOVE FIELD {}{};", item.name, on_table()),
+        SchemaItemKind::Index => {
+            format!("REMOVE INDEX {}{};", escape_ident(&item.name), on_table())
+        }
+        SchemaItemKind::Event => {
+            format!("REMOVE EVENT {}{};", escape_ident(&item.name), on_table())
+        }
+        SchemaItemKind::Analyzer => format!("REMOVE ANALYZER {};", escape_ident(&item.name)),
+        SchemaItemKind::Function => format!(
+            "REMOVE FUNCTION fn::{};",
+            item.name.trim_start_matches("fn::")
+        ),
+        SchemaItemKind::Param => format!("REMOVE PARAM ${};", item.name.trim_start_matches('$')),
+        SchemaItemKind::Access => {
+            format!("REMOVE ACCESS {} ON DATABASE;", escape_ident(&item.name))
+        }
+        SchemaItemKind::User => format!("REMOVE USER {} ON DATABASE;", escape_ident(&item.name)),
+        SchemaItemKind::Model => {
+            format!("REMOVE MODEL ml::{};", item.name.trim_start_matches("ml::"))
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    errors::MigrationsError,
+    schema::{self, DatabaseSchema},
+};
+
+/// The number recorded in the migrations table for repeatable migrations, which are not numbered. Versioned migrations
+/// are numbered from 1.
+pub const REPEATABLE_NUMBER: u32 = 0;
+
+/// A row of the migrations history table.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Migration {
+    pub file_name: String,
+    /// The number of the migration, or [`REPEATABLE_NUMBER`] for a repeatable migration.
+    pub number: u32,
+    /// When the migration ran. `None` if the migration was recorded when the schema was created, since the schema
+    /// files already contain it.
+    pub date_ran: Option<surrealdb::sql::Datetime>,
+    /// The checksum of the migration file when it was recorded. `None` for rows recorded before checksums existed.
+    pub checksum: Option<String>,
+    /// Whether the migration succeeded. Rows recorded before statuses existed are applied.
+    #[serde(default)]
+    pub status: HistoryStatus,
+}
+
+/// The outcome of a migration recorded in the migrations table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryStatus {
+    #[default]
+    Applied,
+    /// The migration ran outside of a transaction and failed, so it may be partially applied. `run` refuses to continue
+    /// until the row is removed with [`crate::MigrationEngine::repair`].
+    Failed,
+}
+
+/// Returns true if a table named `table` is defined in the current database.
+pub(crate) async fn table_exists(
+    client: &Surreal<impl Connection>,
+    table: &str,
+) -> Result<bool, MigrationsError> {
+    let db_info = schema::info(client, "INFO FOR DB;").await?;
+    Ok(DatabaseSchema::from_db_info(&db_info)?
+        .tables
+        .contains_key(table))
+}
+
+/// The statements that define the history table.
+pub(crate) fn define_table_sql(table: &str) -> String {
+    format!(
+        r#"
+        DEFINE TABLE {table} SCHEMAFULL;
+
+        DEFINE FIELD fileName ON TABLE {table} TYPE string;
+        DEFINE FIELD number ON TABLE {table} TYPE int;
+        DEFINE FIELD dateRan ON TABLE {table} TYPE option<datetime>;
+        DEFINE FIELD checksum ON TABLE {table} TYPE option<string>;
+        DEFINE FIELD status ON TABLE {table} TYPE option<string>;
+        "#,
+        table = escape_ident(table)
+    )
+}
+
+/// Adds the fields introduced after the history table was first released to an existing history table.
+pub(crate) fn upgrade_table_sql(table: &str) -> String {
+    format!(
+        r#"
+        DEFINE FIELD IF NOT EXISTS checksum ON TABLE {table} TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS status ON TABLE {table} TYPE option<string>;
+        "#,
+        table = escape_ident(table)
+    )
+}
+
+pub(crate) fn insert_sql(table: &str, binding: &str) -> String {
+    format!("INSERT INTO {} ${};", escape_ident(table), binding)
+}
+
+/// Replaces the row of the repeatable migration bound to `binding` with it.
+pub(crate) fn replace_repeatable_sql(table: &str, binding: &str) -> String {
+    format!(
+        "DELETE {table} WHERE number = {REPEATABLE_NUMBER} AND fileName = ${binding}.fileName; INSERT INTO {table} ${binding};",
+        table = escape_ident(table),
+    )
+}
+
+pub(crate) async fn select_all(
+    client: &Surreal<impl Connection>,
+    table: &str,
+) -> Result<Vec<Migration>, MigrationsError> {
+    let sql = format!("SELECT * FROM {};", escape_ident(table));
+    let db_migrations: Vec<Migration> = client.query(sql).await?.take(0)?;
+    Ok(db_migrations)
+}
+
+/// Escapes `ident` with backticks unless it is a plain identifier.
+pub(crate) fn escape_ident(ident: &str) -> String {
+    let is_plain = !ident.is_empty()
+        && !ident.starts_with(|c: char| c.is_ascii_digit())
+        && ident.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_plain {
+        ident.to_owned()
+    } else {
+        format!("`{}`", ident.replace('\\', "\\\\").replace('`', "\\`"))
+    }
+}
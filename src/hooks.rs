@@ -0,0 +1,94 @@
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    engine::MigrationEngine, errors::MigrationsError, files::SqlFile, rust_migration::BoxError,
+};
+
+/// The point of a run a hook is registered for, see [`MigrationEngine::hook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HookEvent {
+    /// Before anything is sent to the database. The context has every file the run executes.
+    BeforeRun,
+    /// After the schema files ran and the migrations table was created. The context has the schema files.
+    AfterSchema,
+    /// Before a migration runs. The context has the migration.
+    BeforeMigration,
+    /// After a migration ran and was recorded. The context has the migration.
+    AfterMigration,
+    /// After the run failed, including when taking the lock, planning or a hook failed. The context has the error and
+    /// every file the run executes, or no files if the run failed before it was planned.
+    Failure,
+    /// After every file ran. The context has every file the run executed.
+    AfterRun,
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HookEvent::BeforeRun => "before run",
+            HookEvent::AfterSchema => "after schema",
+            HookEvent::BeforeMigration => "before migration",
+            HookEvent::AfterMigration => "after migration",
+            HookEvent::Failure => "failure",
+            HookEvent::AfterRun => "after run",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What a hook is called for.
+#[derive(Debug, Clone)]
+pub struct HookContext {
+    pub event: HookEvent,
+    /// The files the event is about, see [`HookEvent`].
+    pub files: Vec<SqlFile>,
+    /// The error that stopped the run, for [`HookEvent::Failure`].
+    pub error: Option<String>,
+}
+
+type HookFuture = Pin<Box<dyn Future<Output = Result<(), BoxError>> + Send>>;
+
+/// A callback registered with [`MigrationEngine::hook`].
+pub(crate) struct Hook<C: Connection> {
+    event: HookEvent,
+    call: Arc<dyn Fn(HookContext, Surreal<C>) -> HookFuture + Send + Sync>,
+}
+
+impl<C: Connection> Hook<C> {
+    pub(crate) fn new<F, Fut>(event: HookEvent, hook: F) -> Self
+    where
+        F: Fn(HookContext, Surreal<C>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), BoxError>> + Send + 'static,
+    {
+        Self {
+            event,
+            call: Arc::new(move |context, client| Box::pin(hook(context, client))),
+        }
+    }
+}
+
+impl<C: Connection> MigrationEngine<C> {
+    /// Calls the hooks registered for `event` in the order they were registered. The first failing hook stops the
+    /// others.
+    pub(crate) async fn call_hooks(
+        &self,
+        client: &Surreal<C>,
+        event: HookEvent,
+        files: &[SqlFile],
+        error: Option<&MigrationsError>,
+    ) -> Result<(), MigrationsError> {
+        for hook in self.hooks.iter().filter(|hook| hook.event == event) {
+            let context = HookContext {
+                event,
+                files: files.to_vec(),
+                error: error.map(ToString::to_string),
+            };
+            (hook.call)(context, client.clone())
+                .await
+                .map_err(|error| MigrationsError::HookFailed { event, error })?;
+        }
+        Ok(())
+    }
+}
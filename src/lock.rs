@@ -0,0 +1,141 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use surrealdb::{Connection, Surreal};
+
+use crate::{engine::MigrationEngine, errors::MigrationsError, history};
+
+/// How the engine takes the advisory lock that keeps several processes, e.g. replicas of an application that all call
+/// `run` at startup, from migrating the same database at the same time.
+///
+/// The lock is a record in the `{migrations_table}_lock` table holding the owner, when it was acquired and when it
+/// expires. A lock past its expiry is considered stale, e.g. because its owner crashed, and is broken by the next
+/// process that tries to take it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockPolicy {
+    /// How long the lock is valid for. Must be longer than the slowest migration run, otherwise another process may
+    /// break the lock while migrations are still running.
+    pub ttl: Duration,
+    /// How long to wait for a lock held by another process before failing with [`MigrationsError::MigrationLocked`].
+    pub wait: Duration,
+    /// How long to sleep between attempts to take the lock.
+    pub retry_interval: Duration,
+}
+
+impl Default for LockPolicy {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(10 * 60),
+            wait: Duration::from_secs(60),
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl<C: Connection> MigrationEngine<C> {
+    /// Runs `action` while holding the migration lock, if the engine has a [`LockPolicy`]. `action` is only polled once
+    /// the lock is taken, so anything it reads from the database reflects the migrations of the previous lock holder.
+    pub(crate) async fn locked<T>(
+        &self,
+        client: &Surreal<C>,
+        action: impl Future<Output = Result<T, MigrationsError>>,
+    ) -> Result<T, MigrationsError> {
+        let Some(policy) = self.lock else {
+            return action.await;
+        };
+        let owner = self.acquire_lock(client, &policy).await?;
+        let result = action.await;
+        let released = self.release_lock(client, &owner).await;
+        let value = result?;
+        released?;
+        Ok(value)
+    }
+
+    /// The table holding the lock record.
+    pub(crate) fn lock_table(&self) -> String {
+        format!("{}_lock", self.migrations_table)
+    }
+
+    fn lock_record(&self) -> String {
+        format!("{}:global", history::escape_ident(&self.lock_table()))
+    }
+
+    /// Takes the lock, waiting for the current holder according to the policy. Returns the owner it was taken as.
+    async fn acquire_lock(
+        &self,
+        client: &Surreal<C>,
+        policy: &LockPolicy,
+    ) -> Result<String, MigrationsError> {
+        let owner = lock_owner();
+        let record = self.lock_record();
+        // Breaking a stale lock is conditional on it still being expired, so a lock another process took in the
+        // meantime is left alone.
+        let acquire_sql = format!(
+            r#"
+            DELETE {record} WHERE expiresAt < time::now();
+            CREATE {record} SET owner = $owner, acquiredAt = time::now(), expiresAt = time::now() + {ttl}ms RETURN NONE;
+            "#,
+            ttl = policy.ttl.as_millis()
+        );
+        let deadline = Instant::now() + policy.wait;
+        loop {
+            let created = client
+                .query(acquire_sql.as_str())
+                .bind(("owner", owner.clone()))
+                .await?
+                .check();
+            let error = match created {
+                Ok(_) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("Acquired migration lock as '{}'", owner);
+                    return Ok(owner);
+                }
+                Err(error) => error,
+            };
+
+            let holder: Option<String> = client
+                .query(format!("SELECT VALUE owner FROM {record};"))
+                .await?
+                .take(0)?;
+            if Instant::now() >= deadline {
+                return Err(match holder {
+                    Some(holder) if holder != owner => MigrationsError::MigrationLocked {
+                        owner: holder,
+                        wait: policy.wait,
+                    },
+                    // Nobody holds the lock, so the lock could not be created for another reason.
+                    _ => error.into(),
+                });
+            }
+            #[cfg(feature = "tracing")]
+            if let Some(holder) = &holder {
+                tracing::info!("Waiting for the migration lock held by '{}'", holder);
+            }
+            tokio::time::sleep(policy.retry_interval).await;
+        }
+    }
+
+    /// Releases the lock if it is still held by `owner`.
+    async fn release_lock(&self, client: &Surreal<C>, owner: &str) -> Result<(), MigrationsError> {
+        client
+            .query(format!(
+                "DELETE {} WHERE owner = $owner;",
+                self.lock_record()
+            ))
+            .bind(("owner", owner.to_owned()))
+            .await?
+            .check()?;
+        Ok(())
+    }
+}
+
+/// A name for this attempt to take the lock that is unique across processes.
+fn lock_owner() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    format!("{}-{}", std::process::id(), nanos)
+}
@@ -0,0 +1,567 @@
+use std::{slice, time::Duration};
+
+use chrono::Utc;
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    engine::{MigrationEngine, TransactionMode},
+    errors::MigrationsError,
+    execute::{execute, QueryChunk},
+    files::{get_sql_files, MigrationKind, SqlFile},
+    history::{self, HistoryStatus, Migration, REPEATABLE_NUMBER},
+    hooks::HookEvent,
+};
+
+/// Which branch [`MigrationEngine::run`] would take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanAction {
+    /// The migrations table does not exist. The schema files are applied, the migrations table is created and every
+    /// migration file is recorded in it without being ran.
+    CreateSchema,
+    /// The migrations table exists and there are migration files that have not ran yet, or repeatable files that are
+    /// new or changed.
+    ApplyMigrations,
+    /// Nothing would run: the migrations table exists, every migration file up to the target has already ran and no
+    /// repeatable file changed.
+    UpToDate,
+}
+
+/// How far [`MigrationEngine::migrate_to`] migrates. A `u32` converts into [`MigrationTarget::Version`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MigrationTarget {
+    /// Every pending migration, like [`MigrationEngine::run`].
+    #[default]
+    Latest,
+    /// The pending migrations numbered up to and including this version.
+    Version(u32),
+    /// The next pending migrations, at most this many.
+    Steps(usize),
+}
+
+impl From<u32> for MigrationTarget {
+    fn from(version: u32) -> Self {
+        MigrationTarget::Version(version)
+    }
+}
+
+/// What [`MigrationEngine::run`] would do, computed without writing anything to the database.
+#[derive(Debug, Clone)]
+pub struct MigrationPlan {
+    pub action: PlanAction,
+    /// The files that would be executed, in order. These are the schema files for [`PlanAction::CreateSchema`] and the
+    /// pending migration files for [`PlanAction::ApplyMigrations`], followed by the repeatable files that are new or
+    /// changed.
+    pub files: Vec<SqlFile>,
+    /// The rows that would be inserted into the migrations table.
+    pub history: Vec<Migration>,
+    batches: Vec<Batch>,
+}
+
+/// A single request to the database.
+#[derive(Debug, Clone)]
+struct Batch {
+    queries: Vec<QueryChunk>,
+    /// Indexes into [`MigrationPlan::history`] of the rows the batch inserts.
+    history: Vec<usize>,
+    /// The `@timeout` of the file the batch runs, and its name.
+    timeout: Option<(String, Duration)>,
+    /// The number of the Rust migration the batch runs instead of sending `queries`, which then only describe it.
+    rust_migration: Option<u32>,
+    /// The index into [`MigrationPlan::history`] of the row recorded as failed if the batch fails. Only set for files
+    /// that run outside of a transaction, since those may be left partially applied.
+    failure: Option<usize>,
+    /// The migrations whose before-migration hooks fire before the batch is sent.
+    starts: Vec<SqlFile>,
+    /// The migrations whose after-migration hooks fire once the batch succeeded.
+    finishes: Vec<SqlFile>,
+    /// The schema files, set on the batch that completes the schema creation, for the after-schema hooks.
+    schema: Option<Vec<SqlFile>>,
+}
+
+impl Batch {
+    fn new(queries: Vec<QueryChunk>, history: Vec<usize>) -> Self {
+        Self {
+            queries,
+            history,
+            timeout: None,
+            rust_migration: None,
+            failure: None,
+            starts: Vec::new(),
+            finishes: Vec::new(),
+            schema: None,
+        }
+    }
+}
+
+impl MigrationPlan {
+    /// The combined SurrealQL that would be sent. The rows in [`MigrationPlan::history`] are bound as `$migration0`,
+    /// `$migration1`, ... in order.
+    pub fn sql(&self) -> String {
+        self.batches
+            .iter()
+            .flat_map(|batch| batch.queries.iter())
+            .map(|query| query.sql.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Returns true if running the plan would not change the database.
+    pub fn is_empty(&self) -> bool {
+        self.action == PlanAction::UpToDate
+    }
+
+    fn up_to_date() -> Self {
+        Self {
+            action: PlanAction::UpToDate,
+            files: Vec::new(),
+            history: Vec::new(),
+            batches: Vec::new(),
+        }
+    }
+}
+
+impl<C: Connection> MigrationEngine<C> {
+    /// Computes what [`MigrationEngine::run`] would do. Performs the same checks as `run` but only reads from the
+    /// database.
+    pub async fn plan(&self, client: &Surreal<C>) -> Result<MigrationPlan, MigrationsError> {
+        self.plan_to(client, MigrationTarget::Latest).await
+    }
+
+    /// Computes what [`MigrationEngine::migrate_to`] would do. Only reads from the database.
+    pub async fn plan_to(
+        &self,
+        client: &Surreal<C>,
+        target: impl Into<MigrationTarget>,
+    ) -> Result<MigrationPlan, MigrationsError> {
+        let target = target.into();
+        if history::table_exists(client, &self.migrations_table).await? {
+            self.plan_new_migrations(client, target).await
+        } else {
+            self.check_database_is_empty(client).await?;
+            self.plan_schema_creation(target).await
+        }
+    }
+
+    /// Runs a plan computed by [`MigrationEngine::plan`] and calls the hooks around it, except for the failure hooks,
+    /// which [`MigrationEngine::migrate_to`] calls. Batches are sent one after another and the first failing batch
+    /// stops the run. A file that fails outside of a transaction is recorded as [`HistoryStatus::Failed`].
+    pub(crate) async fn execute_plan(
+        &self,
+        client: &Surreal<C>,
+        plan: &MigrationPlan,
+    ) -> Result<(), MigrationsError> {
+        if plan.is_empty() {
+            return Ok(());
+        }
+
+        #[cfg(feature = "tracing")]
+        for file in plan.files.iter() {
+            tracing::info!("Running '{}'", file.file_name);
+        }
+
+        self.execute_batches(client, plan).await?;
+        self.call_hooks(client, HookEvent::AfterRun, &plan.files, None)
+            .await
+    }
+
+    async fn execute_batches(
+        &self,
+        client: &Surreal<C>,
+        plan: &MigrationPlan,
+    ) -> Result<(), MigrationsError> {
+        self.call_hooks(client, HookEvent::BeforeRun, &plan.files, None)
+            .await?;
+        for batch in plan.batches.iter() {
+            for file in batch.starts.iter() {
+                self.call_hooks(
+                    client,
+                    HookEvent::BeforeMigration,
+                    slice::from_ref(file),
+                    None,
+                )
+                .await?;
+            }
+            let result = self.execute_batch(client, plan, batch).await;
+            if let (Err(_), Some(index)) = (&result, batch.failure) {
+                self.record_failure(client, &plan.history[index]).await;
+            }
+            result?;
+            if let Some(schema) = &batch.schema {
+                self.call_hooks(client, HookEvent::AfterSchema, schema, None)
+                    .await?;
+            }
+            for file in batch.finishes.iter() {
+                self.call_hooks(
+                    client,
+                    HookEvent::AfterMigration,
+                    slice::from_ref(file),
+                    None,
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn execute_batch(
+        &self,
+        client: &Surreal<C>,
+        plan: &MigrationPlan,
+        batch: &Batch,
+    ) -> Result<(), MigrationsError> {
+        if let Some(number) = batch.rust_migration {
+            let rust_migration = self
+                .rust_migrations
+                .iter()
+                .find(|rust_migration| rust_migration.number == number)
+                .expect("Rust migrations are planned from the registered ones");
+            return rust_migration.run(client).await.map_err(|error| {
+                MigrationsError::RustMigrationFailed {
+                    file_name: rust_migration.name.clone(),
+                    error,
+                }
+            });
+        }
+        let bindings = batch
+            .history
+            .iter()
+            .map(|index| (binding(*index), plan.history[*index].clone()))
+            .collect();
+        match &batch.timeout {
+            Some((file_name, timeout)) => {
+                tokio::time::timeout(*timeout, execute(client, &batch.queries, bindings))
+                    .await
+                    .map_err(|_| MigrationsError::MigrationTimedOut {
+                        file_name: file_name.clone(),
+                        timeout: *timeout,
+                    })?
+            }
+            None => execute(client, &batch.queries, bindings).await,
+        }
+    }
+
+    /// Records the history row `migration` as failed. An error while recording it is ignored, so that the error of the
+    /// migration itself is returned.
+    async fn record_failure(&self, client: &Surreal<C>, migration: &Migration) {
+        let failed = Migration {
+            status: HistoryStatus::Failed,
+            ..migration.clone()
+        };
+        let sql = if failed.number == REPEATABLE_NUMBER {
+            history::replace_repeatable_sql(&self.migrations_table, &binding(0))
+        } else {
+            history::insert_sql(&self.migrations_table, &binding(0))
+        };
+        let _ = execute(client, &[QueryChunk::new(sql)], vec![(binding(0), failed)]).await;
+    }
+
+    /// Plans creating the schema and the migrations table, for when the migrations table does not exist.
+    /// The schema is created in a single transaction unless the transaction mode is [`TransactionMode::None`].
+    ///
+    /// The schema files contain every migration, so creating the schema counts as a single step and a version target
+    /// must not be older than the newest migration.
+    async fn plan_schema_creation(
+        &self,
+        target: MigrationTarget,
+    ) -> Result<MigrationPlan, MigrationsError> {
+        let schemas = get_sql_files(self.schema_source.as_ref(), &self.validation).await?;
+
+        let migrations = self.migration_files()?;
+        match target {
+            MigrationTarget::Latest => {}
+            MigrationTarget::Version(version) => {
+                let newer = migrations.iter().find(|migration| {
+                    migration.number > version && self.runs_in_environment(migration)
+                });
+                if let Some(newer) = newer {
+                    return Err(MigrationsError::TargetBeforeSchema {
+                        target: version,
+                        file_name: newer.file_name.clone(),
+                    });
+                }
+            }
+            MigrationTarget::Steps(0) => return Ok(MigrationPlan::up_to_date()),
+            MigrationTarget::Steps(_) => {}
+        }
+        let mut repeatables = self.repeatable_files()?;
+        repeatables.retain(|repeatable| self.runs_in_environment(repeatable));
+
+        let mut migrations_to_insert = self.recorded_migrations(migrations, u32::MAX)?;
+
+        let history_indexes: Vec<usize> = (0..migrations_to_insert.len()).collect();
+        let mut history_queries = vec![QueryChunk::new(history::define_table_sql(
+            &self.migrations_table,
+        ))];
+        history_queries.extend(
+            history_indexes
+                .iter()
+                .map(|index| self.insert_query(*index)),
+        );
+
+        let mut batches = match self.transaction_mode {
+            TransactionMode::Single | TransactionMode::PerMigration => {
+                let mut queries = vec![QueryChunk::new("BEGIN TRANSACTION;")];
+                queries.extend(file_queries(&schemas));
+                queries.extend(history_queries);
+                queries.push(QueryChunk::new("COMMIT TRANSACTION;"));
+                vec![Batch {
+                    schema: Some(schemas.clone()),
+                    ..Batch::new(queries, history_indexes)
+                }]
+            }
+            TransactionMode::None => vec![
+                Batch::new(file_queries(&schemas).collect(), Vec::new()),
+                Batch {
+                    schema: Some(schemas.clone()),
+                    ..Batch::new(history_queries, history_indexes)
+                },
+            ],
+        };
+
+        // Repeatable files are not part of the schema files, so they run once the schema exists.
+        let date_ran = surrealdb::sql::Datetime::from(Utc::now());
+        batches.extend(self.migration_batches(&repeatables, migrations_to_insert.len()));
+        migrations_to_insert.extend(
+            repeatables
+                .iter()
+                .map(|repeatable| self.history_row(repeatable, &date_ran)),
+        );
+        let mut files = schemas;
+        files.extend(repeatables);
+
+        Ok(MigrationPlan {
+            action: PlanAction::CreateSchema,
+            files,
+            history: migrations_to_insert,
+            batches,
+        })
+    }
+
+    /// Plans running the migration files that are not in the migrations table yet.
+    async fn plan_new_migrations(
+        &self,
+        client: &Surreal<C>,
+        target: MigrationTarget,
+    ) -> Result<MigrationPlan, MigrationsError> {
+        let db_migrations = history::select_all(client, &self.migrations_table).await?;
+
+        let file_migrations = self.migration_files()?;
+
+        let mut file_migrations = self.pending_migrations(&db_migrations, file_migrations)?;
+        file_migrations.retain(|migration| self.runs_in_environment(migration));
+        let pending = file_migrations.len();
+        match target {
+            MigrationTarget::Latest => {}
+            MigrationTarget::Version(version) => {
+                file_migrations.retain(|migration| migration.number <= version)
+            }
+            MigrationTarget::Steps(0) => return Ok(MigrationPlan::up_to_date()),
+            MigrationTarget::Steps(steps) => file_migrations.truncate(steps),
+        }
+        // Repeatable migrations may depend on the newest versioned migrations, so they only run once every versioned
+        // migration is applied.
+        if file_migrations.len() == pending {
+            let mut repeatables = self.pending_repeatables(&db_migrations)?;
+            repeatables.retain(|repeatable| self.runs_in_environment(repeatable));
+            file_migrations.extend(repeatables);
+        }
+
+        if file_migrations.is_empty() {
+            return Ok(MigrationPlan::up_to_date());
+        }
+
+        let date_ran = surrealdb::sql::Datetime::from(Utc::now());
+        let new_migration_table_entries: Vec<Migration> = file_migrations
+            .iter()
+            .map(|migration| self.history_row(migration, &date_ran))
+            .collect();
+
+        let mut batches = vec![Batch::new(
+            vec![QueryChunk::new(history::upgrade_table_sql(
+                &self.migrations_table,
+            ))],
+            Vec::new(),
+        )];
+        batches.extend(self.migration_batches(&file_migrations, 0));
+
+        Ok(MigrationPlan {
+            action: PlanAction::ApplyMigrations,
+            files: file_migrations,
+            history: new_migration_table_entries,
+            batches,
+        })
+    }
+
+    /// Groups the migration files and the inserts of their history rows into requests according to the transaction
+    /// mode and the files' directives. The history row of `files[i]` is expected at index `first_history + i`.
+    ///
+    /// Rust migrations and files with `@no_transaction` run outside of a transaction and files with `@timeout` run in
+    /// their own request, so in [`TransactionMode::Single`] they split the pending migrations into several
+    /// transactions.
+    fn migration_batches(&self, files: &[SqlFile], first_history: usize) -> Vec<Batch> {
+        let mut batches = Vec::new();
+        let mut transaction: Vec<usize> = Vec::new();
+        for (index, file) in files.iter().enumerate() {
+            let transactional = file.kind != MigrationKind::Rust
+                && self.transaction_mode != TransactionMode::None
+                && !file.directives.no_transaction;
+            if transactional
+                && self.transaction_mode == TransactionMode::Single
+                && file.directives.timeout.is_none()
+            {
+                transaction.push(index);
+                continue;
+            }
+            batches.extend(self.transaction_batch(files, &transaction, first_history));
+            transaction.clear();
+
+            let timeout = file
+                .directives
+                .timeout
+                .map(|timeout| (file.file_name.clone(), timeout));
+            if transactional {
+                batches.extend(
+                    self.transaction_batch(files, &[index], first_history)
+                        .map(|batch| Batch { timeout, ..batch }),
+                );
+            } else {
+                // The history row is sent separately so that it is only inserted if every statement of the file
+                // succeeded.
+                let history = first_history + index;
+                batches.push(match file.kind {
+                    MigrationKind::Surql | MigrationKind::Repeatable => Batch {
+                        timeout,
+                        failure: Some(history),
+                        starts: vec![file.clone()],
+                        ..Batch::new(vec![file_query(file)], Vec::new())
+                    },
+                    MigrationKind::Rust => Batch {
+                        rust_migration: Some(file.number),
+                        failure: Some(history),
+                        starts: vec![file.clone()],
+                        ..Batch::new(
+                            vec![QueryChunk::new(format!(
+                                "-- Rust migration '{}'",
+                                file.file_name
+                            ))],
+                            Vec::new(),
+                        )
+                    },
+                });
+                batches.push(Batch {
+                    finishes: vec![file.clone()],
+                    ..Batch::new(vec![self.history_query(file, history)], vec![history])
+                });
+            }
+        }
+        batches.extend(self.transaction_batch(files, &transaction, first_history));
+        batches
+    }
+
+    /// A transaction that runs `files[i]` for each of `indexes` and records their history rows, which start at
+    /// `first_history`.
+    fn transaction_batch(
+        &self,
+        files: &[SqlFile],
+        indexes: &[usize],
+        first_history: usize,
+    ) -> Option<Batch> {
+        if indexes.is_empty() {
+            return None;
+        }
+        let mut queries = vec![QueryChunk::new("BEGIN TRANSACTION;")];
+        queries.extend(indexes.iter().map(|index| file_query(&files[*index])));
+        queries.extend(
+            indexes
+                .iter()
+                .map(|index| self.history_query(&files[*index], first_history + index)),
+        );
+        queries.push(QueryChunk::new("COMMIT TRANSACTION;"));
+        let migrations: Vec<SqlFile> = indexes.iter().map(|index| files[*index].clone()).collect();
+        Some(Batch {
+            starts: migrations.clone(),
+            finishes: migrations,
+            ..Batch::new(
+                queries,
+                indexes.iter().map(|index| first_history + index).collect(),
+            )
+        })
+    }
+
+    /// The history rows recorded without running anything for the archived migrations and the `migrations` numbered up
+    /// to `through` that run in the configured environment, since the database already contains them.
+    pub(crate) fn recorded_migrations(
+        &self,
+        migrations: Vec<SqlFile>,
+        through: u32,
+    ) -> Result<Vec<Migration>, MigrationsError> {
+        // Squashed migrations are part of the schema files, so they are recorded too.
+        let archived_migrations = self
+            .archived_migrations()?
+            .into_iter()
+            .filter(|archived| archived.number <= through)
+            .map(|archived| Migration {
+                file_name: archived.file_name,
+                number: archived.number,
+                date_ran: None,
+                checksum: None,
+                status: HistoryStatus::Applied,
+            });
+        Ok(archived_migrations
+            .chain(
+                migrations
+                    .into_iter()
+                    .filter(|migration| {
+                        migration.number <= through && self.runs_in_environment(migration)
+                    })
+                    .map(|migration| Migration {
+                        checksum: migration.recorded_checksum(self.checksum_mode),
+                        file_name: migration.file_name,
+                        number: migration.number,
+                        date_ran: None,
+                        status: HistoryStatus::Applied,
+                    }),
+            )
+            .collect())
+    }
+
+    pub(crate) fn insert_query(&self, index: usize) -> QueryChunk {
+        QueryChunk::new(history::insert_sql(&self.migrations_table, &binding(index)))
+    }
+
+    /// Records the history row of `file` at `index`. A repeatable file replaces the row of its previous run.
+    fn history_query(&self, file: &SqlFile, index: usize) -> QueryChunk {
+        match file.kind {
+            MigrationKind::Repeatable => QueryChunk::new(history::replace_repeatable_sql(
+                &self.migrations_table,
+                &binding(index),
+            )),
+            MigrationKind::Surql | MigrationKind::Rust => self.insert_query(index),
+        }
+    }
+
+    /// The history row recorded when `file` runs.
+    fn history_row(&self, file: &SqlFile, date_ran: &surrealdb::sql::Datetime) -> Migration {
+        Migration {
+            file_name: file.file_name.clone(),
+            number: file.number,
+            date_ran: Some(date_ran.clone()),
+            checksum: file.recorded_checksum(self.checksum_mode),
+            status: HistoryStatus::Applied,
+        }
+    }
+}
+
+/// The name of the parameter the history row at `index` is bound to.
+pub(crate) fn binding(index: usize) -> String {
+    format!("migration{}", index)
+}
+
+fn file_query(file: &SqlFile) -> QueryChunk {
+    QueryChunk::from_file(file.file_name.as_str(), file.sql.as_str())
+}
+
+/// Sends each file as its own statement group.
+fn file_queries(files: &[SqlFile]) -> impl Iterator<Item = QueryChunk> + '_ {
+    files.iter().map(file_query)
+}
@@ -0,0 +1,229 @@
+use std::fmt;
+
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    engine::MigrationEngine,
+    errors::MigrationsError,
+    execute::{execute, QueryChunk},
+    history::{self, HistoryStatus, Migration, REPEATABLE_NUMBER},
+};
+
+/// A fix to the migrations table, see [`MigrationEngine::plan_repair`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairOperation {
+    /// Sets the recorded checksums to the checksums of the current files, e.g. after a deliberate edit of an applied
+    /// migration. Rows without a file are left as they are.
+    RecomputeChecksums,
+    /// Deletes the rows of failed migrations, so that they run again. Fix whatever they partially applied first.
+    DeleteFailed,
+    /// Changes the recorded `fileName` of the rows named `from` to `to`, after the file was renamed.
+    Rename { from: String, to: String },
+    /// Changes the recorded number of the rows numbered `from` to `to`, after the files were renumbered.
+    Renumber { from: u32, to: u32 },
+}
+
+/// A row of the migrations table changed by a repair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairChange {
+    /// The row as it is recorded.
+    pub before: Migration,
+    /// The row after the repair, or `None` if it is deleted.
+    pub after: Option<Migration>,
+}
+
+/// The changes a repair makes, computed by [`MigrationEngine::plan_repair`] without writing anything to the database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairPlan {
+    pub operation: RepairOperation,
+    pub changes: Vec<RepairChange>,
+}
+
+impl RepairPlan {
+    /// Returns true if the repair would not change the migrations table.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for RepairChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let before = &self.before;
+        let Some(after) = &self.after else {
+            return write!(f, "delete {:>4}  {}", before.number, before.file_name);
+        };
+        write!(f, "update {:>4}  {}:", before.number, before.file_name)?;
+        if after.file_name != before.file_name {
+            write!(
+                f,
+                " fileName '{}' -> '{}'",
+                before.file_name, after.file_name
+            )?;
+        }
+        if after.number != before.number {
+            write!(f, " number {} -> {}", before.number, after.number)?;
+        }
+        if after.checksum != before.checksum {
+            write!(
+                f,
+                " checksum '{}' -> '{}'",
+                before.checksum.as_deref().unwrap_or("none"),
+                after.checksum.as_deref().unwrap_or("none")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: Connection> MigrationEngine<C> {
+    /// Computes the changes `operation` makes to the migrations table, so they can be reviewed before they are made
+    /// with [`MigrationEngine::repair`]. Only reads from the database. A rename or renumber that would give a row the
+    /// file name or number of another row fails with [`MigrationsError::RepairConflict`].
+    pub async fn plan_repair(
+        &self,
+        client: &Surreal<C>,
+        operation: RepairOperation,
+    ) -> Result<RepairPlan, MigrationsError> {
+        let db_migrations = if history::table_exists(client, &self.migrations_table).await? {
+            history::select_all(client, &self.migrations_table).await?
+        } else {
+            Vec::new()
+        };
+        let recorded = db_migrations.clone();
+
+        let changes = match &operation {
+            RepairOperation::RecomputeChecksums => {
+                let migration_files = self.migration_files()?;
+                let repeatable_files = self.repeatable_files()?;
+                db_migrations
+                    .into_iter()
+                    .filter(|db_migration| db_migration.status == HistoryStatus::Applied)
+                    .filter_map(|db_migration| {
+                        let file = if db_migration.number == REPEATABLE_NUMBER {
+                            repeatable_files
+                                .iter()
+                                .find(|file| file.file_name == db_migration.file_name)
+                        } else {
+                            migration_files
+                                .iter()
+                                .find(|file| file.number == db_migration.number)
+                        }?;
+                        let checksum = file.recorded_checksum(self.checksum_mode)?;
+                        if db_migration.checksum.as_deref() == Some(checksum.as_str()) {
+                            return None;
+                        }
+                        let after = Migration {
+                            checksum: Some(checksum),
+                            ..db_migration.clone()
+                        };
+                        Some(RepairChange {
+                            before: db_migration,
+                            after: Some(after),
+                        })
+                    })
+                    .collect()
+            }
+            RepairOperation::DeleteFailed => db_migrations
+                .into_iter()
+                .filter(|db_migration| db_migration.status == HistoryStatus::Failed)
+                .map(|db_migration| RepairChange {
+                    before: db_migration,
+                    after: None,
+                })
+                .collect(),
+            RepairOperation::Rename { from, to } => db_migrations
+                .into_iter()
+                .filter(|db_migration| &db_migration.file_name == from)
+                .map(|db_migration| RepairChange {
+                    after: Some(Migration {
+                        file_name: to.clone(),
+                        ..db_migration.clone()
+                    }),
+                    before: db_migration,
+                })
+                .collect(),
+            RepairOperation::Renumber { from, to } => db_migrations
+                .into_iter()
+                .filter(|db_migration| db_migration.number == *from)
+                .map(|db_migration| RepairChange {
+                    after: Some(Migration {
+                        number: *to,
+                        ..db_migration.clone()
+                    }),
+                    before: db_migration,
+                })
+                .collect(),
+        };
+
+        // A row with the number or file name of another row would fail the next run with a mismatch.
+        if matches!(
+            operation,
+            RepairOperation::Rename { .. } | RepairOperation::Renumber { .. }
+        ) {
+            for after in changes.iter().filter_map(|change| change.after.as_ref()) {
+                let conflict = recorded.iter().find(|row| {
+                    !changes.iter().any(|change| change.before == **row)
+                        && (row.file_name == after.file_name
+                            || (row.number == after.number && row.number != REPEATABLE_NUMBER))
+                });
+                if let Some(row) = conflict {
+                    return Err(MigrationsError::RepairConflict {
+                        number: row.number,
+                        file_name: row.file_name.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(RepairPlan { operation, changes })
+    }
+
+    /// Makes the changes of a plan computed by [`MigrationEngine::plan_repair`] in a single transaction. Rows are
+    /// matched by their recorded number and file name, so rows that changed since the plan was computed are left as
+    /// they are.
+    pub async fn repair(
+        &self,
+        client: &Surreal<C>,
+        plan: &RepairPlan,
+    ) -> Result<(), MigrationsError> {
+        self.locked(client, self.repair_unlocked(client, plan))
+            .await
+    }
+
+    async fn repair_unlocked(
+        &self,
+        client: &Surreal<C>,
+        plan: &RepairPlan,
+    ) -> Result<(), MigrationsError> {
+        if plan.is_empty() {
+            return Ok(());
+        }
+
+        let table = history::escape_ident(&self.migrations_table);
+        // Tables created by older versions do not define the fields a repair may write yet.
+        let mut queries = vec![
+            QueryChunk::new("BEGIN TRANSACTION;"),
+            QueryChunk::new(history::upgrade_table_sql(&self.migrations_table)),
+        ];
+        let mut bindings = Vec::new();
+        for (index, change) in plan.changes.iter().enumerate() {
+            let before = format!("before{}", index);
+            let matches_before =
+                format!("number = ${before}.number AND fileName = ${before}.fileName");
+            queries.push(QueryChunk::new(match &change.after {
+                Some(after) => {
+                    let after_binding = format!("after{}", index);
+                    let sql =
+                        format!("UPDATE {table} CONTENT ${after_binding} WHERE {matches_before};");
+                    bindings.push((after_binding, after.clone()));
+                    sql
+                }
+                None => format!("DELETE {table} WHERE {matches_before};"),
+            }));
+            bindings.push((before, change.before.clone()));
+        }
+        queries.push(QueryChunk::new("COMMIT TRANSACTION;"));
+
+        execute(client, &queries, bindings).await
+    }
+}
@@ -0,0 +1,121 @@
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    engine::MigrationEngine,
+    errors::MigrationsError,
+    execute::{execute, QueryChunk},
+    history::{self, HistoryStatus, Migration, REPEATABLE_NUMBER},
+};
+
+impl<C: Connection> MigrationEngine<C> {
+    /// Rolls back every applied migration with a number greater than `version`, newest first. The down files and the
+    /// removal of the rolled back rows from the migrations table happen in a single transaction. Failed migrations are
+    /// left to [`MigrationEngine::repair`].
+    /// Returns the rows that were removed from the migrations table.
+    pub async fn rollback_to(
+        &self,
+        client: &Surreal<C>,
+        version: u32,
+    ) -> Result<Vec<Migration>, MigrationsError> {
+        self.locked(client, self.rollback_to_unlocked(client, version))
+            .await
+    }
+
+    async fn rollback_to_unlocked(
+        &self,
+        client: &Surreal<C>,
+        version: u32,
+    ) -> Result<Vec<Migration>, MigrationsError> {
+        if !history::table_exists(client, &self.migrations_table).await? {
+            return Ok(Vec::new()); // Nothing has ran
+        }
+        let mut db_migrations: Vec<Migration> = history::select_all(client, &self.migrations_table)
+            .await?
+            .into_iter()
+            .filter(|db_migration| {
+                db_migration.number > version && db_migration.status == HistoryStatus::Applied
+            })
+            .collect();
+        db_migrations.sort_by(|a, b| b.number.cmp(&a.number));
+        self.roll_back(client, db_migrations).await
+    }
+
+    /// Rolls back the applied migration with the highest number. Returns the row that was removed from the migrations
+    /// table, or `None` if no migration has ran.
+    pub async fn rollback_last(
+        &self,
+        client: &Surreal<C>,
+    ) -> Result<Option<Migration>, MigrationsError> {
+        self.locked(client, self.rollback_last_unlocked(client))
+            .await
+    }
+
+    async fn rollback_last_unlocked(
+        &self,
+        client: &Surreal<C>,
+    ) -> Result<Option<Migration>, MigrationsError> {
+        if !history::table_exists(client, &self.migrations_table).await? {
+            return Ok(None); // Nothing has ran
+        }
+        let last = history::select_all(client, &self.migrations_table)
+            .await?
+            .into_iter()
+            .filter(|db_migration| {
+                db_migration.number != REPEATABLE_NUMBER
+                    && db_migration.status == HistoryStatus::Applied
+            })
+            .max_by_key(|db_migration| db_migration.number);
+        let Some(last) = last else {
+            return Ok(None);
+        };
+        Ok(self.roll_back(client, vec![last]).await?.pop())
+    }
+
+    /// Runs the down files of `db_migrations` in the given order and removes them from the migrations table.
+    async fn roll_back(
+        &self,
+        client: &Surreal<C>,
+        db_migrations: Vec<Migration>,
+    ) -> Result<Vec<Migration>, MigrationsError> {
+        if db_migrations.is_empty() {
+            return Ok(db_migrations);
+        }
+
+        let file_migrations = self.migration_files()?;
+
+        let mut queries = vec![QueryChunk::new("BEGIN TRANSACTION;")];
+        for db_migration in db_migrations.iter() {
+            let migration_file = file_migrations
+                .iter()
+                .find(|migration_file| migration_file.number == db_migration.number)
+                .ok_or_else(|| MigrationsError::MigrationFileInDbNotLongerExists {
+                    number: db_migration.number,
+                    db_file_name: db_migration.file_name.clone(),
+                })?;
+            let down = migration_file.down.as_ref().ok_or_else(|| {
+                MigrationsError::MissingDownMigration {
+                    file_name: migration_file.file_name.clone(),
+                }
+            })?;
+            queries.push(QueryChunk::from_file(
+                down.file_name.as_str(),
+                down.sql.as_str(),
+            ));
+        }
+
+        let numbers: Vec<u32> = db_migrations
+            .iter()
+            .map(|db_migration| db_migration.number)
+            .collect();
+
+        queries.push(QueryChunk::new(format!(
+            "DELETE {} WHERE number IN $numbers;",
+            history::escape_ident(&self.migrations_table)
+        )));
+        queries.push(QueryChunk::new("COMMIT TRANSACTION;"));
+
+        execute(client, &queries, vec![("numbers".to_owned(), numbers)]).await?;
+
+        Ok(db_migrations)
+    }
+}
@@ -0,0 +1,50 @@
+use std::{error::Error, future::Future, pin::Pin, sync::Arc};
+
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    directives::Directives,
+    files::{MigrationKind, SqlFile},
+};
+
+/// The error returned by a Rust migration.
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+type MigrationFuture = Pin<Box<dyn Future<Output = Result<(), BoxError>> + Send>>;
+
+/// A migration written in Rust, see [`crate::MigrationEngine::rust_migration`].
+pub(crate) struct RustMigration<C: Connection> {
+    pub(crate) number: u32,
+    pub(crate) name: String,
+    run: Arc<dyn Fn(Surreal<C>) -> MigrationFuture + Send + Sync>,
+}
+
+impl<C: Connection> RustMigration<C> {
+    pub(crate) fn new<F, Fut>(number: u32, name: String, migration: F) -> Self
+    where
+        F: Fn(Surreal<C>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), BoxError>> + Send + 'static,
+    {
+        Self {
+            number,
+            name,
+            run: Arc::new(move |client| Box::pin(migration(client))),
+        }
+    }
+
+    pub(crate) async fn run(&self, client: &Surreal<C>) -> Result<(), BoxError> {
+        (self.run)(client.clone()).await
+    }
+
+    /// The migration as a file, so it is ordered, validated and recorded with the `.surql` migrations.
+    pub(crate) fn as_sql_file(&self) -> SqlFile {
+        SqlFile {
+            file_name: self.name.clone(),
+            number: self.number,
+            sql: String::new(),
+            directives: Directives::default(),
+            kind: MigrationKind::Rust,
+            down: None,
+        }
+    }
+}
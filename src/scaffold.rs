@@ -0,0 +1,132 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::MigrationsError,
+    files::{file_number, is_repeatable},
+    source::{DirectorySource, MigrationSource},
+    squash::load_archive,
+};
+
+/// The number of digits new file names are padded to when the directory has no migration files yet.
+const DEFAULT_NUMBER_WIDTH: usize = 4;
+
+/// What [`new_migration`] creates besides the migration file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NewMigrationOptions {
+    /// Also create an empty down file, e.g. `0007_add_age.down.surql`.
+    pub down: bool,
+    /// Start the migration file with a `@description` directive holding the description.
+    pub header: bool,
+}
+
+/// The files created by [`new_migration`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewMigration {
+    pub number: u32,
+    pub path: PathBuf,
+    pub down_path: Option<PathBuf>,
+}
+
+/// Creates the next migration file in `directory`, numbered one above the highest `.surql` file and padded like it,
+/// e.g. `0007_add_age_to_user.surql` for the description `Add age to user`. The directory is created if it does not
+/// exist and existing files are never overwritten.
+pub fn new_migration(
+    directory: impl AsRef<Path>,
+    description: &str,
+    options: NewMigrationOptions,
+) -> Result<NewMigration, MigrationsError> {
+    create_migration(directory.as_ref(), description, options, "", "")
+}
+
+/// Creates the next migration file in `directory` like [`new_migration`], holding `sql` after the header. The down
+/// file, if any, holds `down_sql`.
+pub(crate) fn create_migration(
+    directory: &Path,
+    description: &str,
+    options: NewMigrationOptions,
+    sql: &str,
+    down_sql: &str,
+) -> Result<NewMigration, MigrationsError> {
+    let name = slug(description);
+    if name.is_empty() {
+        return Err(MigrationsError::InvalidMigrationDescription {
+            description: description.to_owned(),
+        });
+    }
+    fs::create_dir_all(directory).map_err(|error| MigrationsError::CannotWriteFile {
+        file_name: directory.display().to_string(),
+        error,
+    })?;
+
+    let source = DirectorySource::new(directory);
+    // Squashed migrations keep their numbers, so their files count even though they were removed.
+    let mut file_names: Vec<String> = load_archive(&source)?
+        .into_iter()
+        .map(|archived| archived.file_name)
+        .collect();
+    file_names.extend(source.file_names()?);
+    let mut last: Option<(u32, usize)> = None;
+    for file_name in file_names {
+        let base_name = file_name.rsplit('/').next().unwrap_or(&file_name);
+        if !base_name.ends_with(".surql") || is_repeatable(&file_name) {
+            continue;
+        }
+        let number = file_number(&file_name)?;
+        if last.is_some_and(|(last_number, _)| last_number >= number) {
+            continue;
+        }
+        let width = base_name.chars().take_while(char::is_ascii_digit).count();
+        last = Some((number, width));
+    }
+    let (last_number, width) = last.unwrap_or((0, DEFAULT_NUMBER_WIDTH));
+    let number = last_number + 1;
+
+    let stem = format!("{:0width$}_{}", number, name);
+    let path = directory.join(format!("{stem}.surql"));
+    let header = if options.header {
+        format!("-- @description {}\n\n", description.trim())
+    } else {
+        String::new()
+    };
+    create_file(&path, &format!("{header}{sql}"))?;
+
+    let down_path = if options.down {
+        let down_path = directory.join(format!("{stem}.down.surql"));
+        create_file(&down_path, &format!("-- Undoes {stem}.surql\n\n{down_sql}"))?;
+        Some(down_path)
+    } else {
+        None
+    };
+
+    Ok(NewMigration {
+        number,
+        path,
+        down_path,
+    })
+}
+
+/// Turns a description into the part of a file name after the number, e.g. `Add age to user` into `add_age_to_user`.
+fn slug(description: &str) -> String {
+    description
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn create_file(path: &Path, contents: &str) -> Result<(), MigrationsError> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|error| MigrationsError::CannotWriteFile {
+            file_name: path.display().to_string(),
+            error,
+        })
+}
@@ -0,0 +1,363 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde_json::Value;
+use surrealdb::{Connection, Surreal};
+
+use crate::{errors::MigrationsError, history};
+
+/// What kind of definition a [`SchemaItem`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SchemaItemKind {
+    Table,
+    Field,
+    Index,
+    Event,
+    Analyzer,
+    Function,
+    Param,
+    Access,
+    User,
+    Model,
+}
+
+impl fmt::Display for SchemaItemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SchemaItemKind::Table => "table",
+            SchemaItemKind::Field => "field",
+            SchemaItemKind::Index => "index",
+            SchemaItemKind::Event => "event",
+            SchemaItemKind::Analyzer => "analyzer",
+            SchemaItemKind::Function => "function",
+            SchemaItemKind::Param => "param",
+            SchemaItemKind::Access => "access",
+            SchemaItemKind::User => "user",
+            SchemaItemKind::Model => "model",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A single definition in a database, e.g. a field of a table.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SchemaItem {
+    pub kind: SchemaItemKind,
+    /// The table of a field, index or event.
+    pub table: Option<String>,
+    pub name: String,
+}
+
+impl fmt::Display for SchemaItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.table {
+            Some(table) => write!(f, "{} {}.{}", self.kind, table, self.name),
+            None => write!(f, "{} {}", self.kind, self.name),
+        }
+    }
+}
+
+/// How a [`SchemaItem`] differs between the expected and the actual database. The definitions are the `DEFINE`
+/// statements reported by `INFO FOR DB` and `INFO FOR TABLE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaChange {
+    /// Defined in the expected database only.
+    Missing { expected: String },
+    /// Defined in the actual database only.
+    Extra { actual: String },
+    /// Defined differently in the two databases.
+    Altered { expected: String, actual: String },
+}
+
+/// A definition that differs between two databases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaDifference {
+    pub item: SchemaItem,
+    pub change: SchemaChange,
+}
+
+impl fmt::Display for SchemaDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.change {
+            SchemaChange::Missing { expected } => {
+                write!(f, "missing {}: expected `{}`", self.item, expected)
+            }
+            SchemaChange::Extra { actual } => write!(f, "extra {}: `{}`", self.item, actual),
+            SchemaChange::Altered { expected, actual } => write!(
+                f,
+                "altered {}: expected `{}`, found `{}`",
+                self.item, expected, actual
+            ),
+        }
+    }
+}
+
+/// The structure of a database, as reported by `INFO FOR DB` and `INFO FOR TABLE`. Every map is keyed by name and
+/// holds the `DEFINE` statement SurrealDB reports for the definition.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatabaseSchema {
+    pub tables: BTreeMap<String, TableSchema>,
+    pub analyzers: BTreeMap<String, String>,
+    pub functions: BTreeMap<String, String>,
+    pub params: BTreeMap<String, String>,
+    /// Accesses, or the scopes and tokens that SurrealDB 1.x defines instead.
+    pub accesses: BTreeMap<String, String>,
+    pub users: BTreeMap<String, String>,
+    pub models: BTreeMap<String, String>,
+}
+
+/// The structure of a table, see [`DatabaseSchema`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableSchema {
+    /// The `DEFINE TABLE` statement.
+    pub definition: String,
+    pub fields: BTreeMap<String, String>,
+    pub indexes: BTreeMap<String, String>,
+    pub events: BTreeMap<String, String>,
+}
+
+impl DatabaseSchema {
+    /// Reads the structure of the current database of `client`.
+    pub async fn read(client: &Surreal<impl Connection>) -> Result<Self, MigrationsError> {
+        let mut schema = Self::from_db_info(&info(client, "INFO FOR DB;").await?)?;
+        for (name, table) in schema.tables.iter_mut() {
+            let sql = format!("INFO FOR TABLE {};", history::escape_ident(name));
+            let definition = std::mem::take(&mut table.definition);
+            *table = TableSchema::from_table_info(definition, &info(client, &sql).await?)?;
+        }
+        Ok(schema)
+    }
+
+    /// Parses the result of `INFO FOR DB`. The tables only hold their definitions, see
+    /// [`TableSchema::from_table_info`] for the rest. Both the SurrealDB 2.x keys, e.g. `tables`, and the abbreviated
+    /// keys of early 1.x versions, e.g. `tb`, are understood, and unknown keys are ignored. Fails if there is no tables
+    /// section or a known section is not an object.
+    pub fn from_db_info(info: &Value) -> Result<Self, MigrationsError> {
+        const STATEMENT: &str = "INFO FOR DB";
+        let info = info_object(info, STATEMENT)?;
+        if !info.contains_key("tables") && !info.contains_key("tb") {
+            return Err(MigrationsError::UnexpectedInfo {
+                statement: STATEMENT.to_owned(),
+                reason: "the result has no tables".to_owned(),
+            });
+        }
+        let mut schema = DatabaseSchema::default();
+        for (key, section) in info.iter() {
+            let definitions = match key.as_str() {
+                "tables" | "tb" => {
+                    let tables = definitions_of(STATEMENT, key, section)?;
+                    schema
+                        .tables
+                        .extend(tables.into_iter().map(|(name, definition)| {
+                            (
+                                name,
+                                TableSchema {
+                                    definition,
+                                    ..Default::default()
+                                },
+                            )
+                        }));
+                    continue;
+                }
+                "analyzers" | "az" => &mut schema.analyzers,
+                "functions" | "fc" => &mut schema.functions,
+                "params" | "pa" => &mut schema.params,
+                "accesses" | "scopes" | "tokens" | "sc" | "dt" => &mut schema.accesses,
+                "users" | "dl" => &mut schema.users,
+                "models" | "ml" => &mut schema.models,
+                _ => continue,
+            };
+            definitions.extend(definitions_of(STATEMENT, key, section)?);
+        }
+        Ok(schema)
+    }
+
+    /// Every definition, keyed by item.
+    pub fn items(&self) -> BTreeMap<SchemaItem, String> {
+        fn insert(
+            items: &mut BTreeMap<SchemaItem, String>,
+            kind: SchemaItemKind,
+            table: Option<&str>,
+            definitions: &BTreeMap<String, String>,
+        ) {
+            for (name, definition) in definitions.iter() {
+                items.insert(
+                    SchemaItem {
+                        kind,
+                        table: table.map(str::to_owned),
+                        name: name.clone(),
+                    },
+                    definition.clone(),
+                );
+            }
+        }
+
+        let mut items = BTreeMap::new();
+        for (name, table) in self.tables.iter() {
+            items.insert(
+                SchemaItem {
+                    kind: SchemaItemKind::Table,
+                    table: None,
+                    name: name.clone(),
+                },
+                table.definition.clone(),
+            );
+            insert(&mut items, SchemaItemKind::Field, Some(name), &table.fields);
+            insert(
+                &mut items,
+                SchemaItemKind::Index,
+                Some(name),
+                &table.indexes,
+            );
+            insert(&mut items, SchemaItemKind::Event, Some(name), &table.events);
+        }
+        insert(&mut items, SchemaItemKind::Analyzer, None, &self.analyzers);
+        insert(&mut items, SchemaItemKind::Function, None, &self.functions);
+        insert(&mut items, SchemaItemKind::Param, None, &self.params);
+        insert(&mut items, SchemaItemKind::Access, None, &self.accesses);
+        insert(&mut items, SchemaItemKind::User, None, &self.users);
+        insert(&mut items, SchemaItemKind::Model, None, &self.models);
+        items
+    }
+
+    /// Compares `self`, as the expected structure, with `actual`. The differences are ordered by item.
+    pub fn diff(&self, actual: &DatabaseSchema) -> Vec<SchemaDifference> {
+        let expected = self.items();
+        let actual = actual.items();
+        let mut differences = Vec::new();
+        for (item, expected_definition) in expected.iter() {
+            let change = match actual.get(item) {
+                None => SchemaChange::Missing {
+                    expected: expected_definition.clone(),
+                },
+                Some(actual_definition) if actual_definition != expected_definition => {
+                    SchemaChange::Altered {
+                        expected: expected_definition.clone(),
+                        actual: actual_definition.clone(),
+                    }
+                }
+                Some(_) => continue,
+            };
+            differences.push(SchemaDifference {
+                item: item.clone(),
+                change,
+            });
+        }
+        for (item, actual_definition) in actual.iter() {
+            if !expected.contains_key(item) {
+                differences.push(SchemaDifference {
+                    item: item.clone(),
+                    change: SchemaChange::Extra {
+                        actual: actual_definition.clone(),
+                    },
+                });
+            }
+        }
+        differences.sort_by(|a, b| a.item.cmp(&b.item));
+        differences
+    }
+}
+
+impl TableSchema {
+    /// Parses the result of `INFO FOR TABLE` for a table defined by `definition`. Like
+    /// [`DatabaseSchema::from_db_info`], both full and abbreviated keys are understood.
+    pub fn from_table_info(definition: String, info: &Value) -> Result<Self, MigrationsError> {
+        let info = info_object(info, "INFO FOR TABLE")?;
+        let mut table = TableSchema {
+            definition,
+            ..Default::default()
+        };
+        for (key, section) in info.iter() {
+            let definitions = match key.as_str() {
+                "fields" | "fd" => &mut table.fields,
+                "indexes" | "ix" => &mut table.indexes,
+                "events" | "ev" => &mut table.events,
+                _ => continue,
+            };
+            definitions.extend(definitions_of("INFO FOR TABLE", key, section)?);
+        }
+        Ok(table)
+    }
+}
+
+/// The structure of the current database without the tables in `ignored_tables`, e.g. the migrations table.
+#[cfg(feature = "kv-mem")]
+pub(crate) async fn read_without(
+    client: &Surreal<impl Connection>,
+    ignored_tables: &[String],
+) -> Result<DatabaseSchema, MigrationsError> {
+    let mut schema = DatabaseSchema::read(client).await?;
+    schema
+        .tables
+        .retain(|name, _| !ignored_tables.contains(name));
+    Ok(schema)
+}
+
+/// Drops the users and accesses that are only defined in the actual database. Like
+/// [`MigrationEngine::baseline`](crate::MigrationEngine::baseline), they are left alone, since they are usually set up
+/// outside of the schema files, e.g. the credentials the application signs in with.
+#[cfg(feature = "kv-mem")]
+pub(crate) fn without_extra_credentials(
+    differences: Vec<SchemaDifference>,
+) -> Vec<SchemaDifference> {
+    differences
+        .into_iter()
+        .filter(|difference| {
+            !matches!(difference.change, SchemaChange::Extra { .. })
+                || !matches!(
+                    difference.item.kind,
+                    SchemaItemKind::User | SchemaItemKind::Access
+                )
+        })
+        .collect()
+}
+
+/// Runs an `INFO` statement and returns what it reports.
+pub(crate) async fn info(
+    client: &Surreal<impl Connection>,
+    sql: &str,
+) -> Result<Value, MigrationsError> {
+    let result: Vec<Value> = client.query(sql).await?.take(0)?;
+    result
+        .into_iter()
+        .next()
+        .ok_or_else(|| MigrationsError::UnexpectedInfo {
+            statement: sql.trim_end_matches(';').to_owned(),
+            reason: "no data was returned".to_owned(),
+        })
+}
+
+fn info_object<'a>(
+    info: &'a Value,
+    statement: &str,
+) -> Result<&'a serde_json::Map<String, Value>, MigrationsError> {
+    info.as_object()
+        .ok_or_else(|| MigrationsError::UnexpectedInfo {
+            statement: statement.to_owned(),
+            reason: "the result is not an object".to_owned(),
+        })
+}
+
+/// The name and definition of each entry of a section of an `INFO` object. Definitions that are not strings, e.g. the
+/// objects of `INFO ... STRUCTURE`, are kept as JSON. Fails if the section is not an object.
+fn definitions_of(
+    statement: &str,
+    key: &str,
+    section: &Value,
+) -> Result<Vec<(String, String)>, MigrationsError> {
+    let section = section
+        .as_object()
+        .ok_or_else(|| MigrationsError::UnexpectedInfo {
+            statement: statement.to_owned(),
+            reason: format!("`{}` is not an object", key),
+        })?;
+    Ok(section
+        .iter()
+        .map(|(name, definition)| {
+            let definition = match definition {
+                Value::String(definition) => definition.clone(),
+                definition => definition.to_string(),
+            };
+            (name.clone(), definition)
+        })
+        .collect())
+}
@@ -0,0 +1,175 @@
+use std::{
+    fs,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use crate::{errors::MigrationsError, squash::ARCHIVE_FILE_NAME};
+
+/// Where migration or schema files come from.
+pub trait MigrationSource: Send + Sync {
+    /// Lists the names of the files, relative to the root of the source and separated with `/`.
+    fn file_names(&self) -> Result<Vec<String>, MigrationsError>;
+
+    /// Loads the contents of a file returned by [`MigrationSource::file_names`].
+    fn load(&self, file_name: &str) -> Result<String, MigrationsError>;
+}
+
+/// Files compiled into the binary with `rust_embed`.
+pub struct EmbeddedSource<F> {
+    _files: PhantomData<fn() -> F>,
+}
+
+impl<F: rust_embed::RustEmbed> EmbeddedSource<F> {
+    pub fn new() -> Self {
+        Self {
+            _files: PhantomData,
+        }
+    }
+}
+
+impl<F: rust_embed::RustEmbed> Default for EmbeddedSource<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: rust_embed::RustEmbed> MigrationSource for EmbeddedSource<F> {
+    fn file_names(&self) -> Result<Vec<String>, MigrationsError> {
+        Ok(F::iter().map(|file_name| file_name.to_string()).collect())
+    }
+
+    fn load(&self, file_name: &str) -> Result<String, MigrationsError> {
+        Ok(String::from_utf8_lossy(
+            F::get(file_name)
+                .ok_or_else(|| MigrationsError::CannotLoadFile {
+                    file_name: file_name.to_owned(),
+                })?
+                .data
+                .as_ref(),
+        )
+        .to_string())
+    }
+}
+
+/// Files read from a directory at runtime, including its subdirectories. Only `.surql` files and the archive are
+/// listed. Entries whose name starts with `.` are skipped, e.g. editor swap files or the `..data` links Kubernetes
+/// creates when a ConfigMap is mounted.
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    path: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl MigrationSource for DirectorySource {
+    fn file_names(&self) -> Result<Vec<String>, MigrationsError> {
+        let mut file_names = Vec::new();
+        let mut directories = vec![self.path.clone()];
+        while let Some(directory) = directories.pop() {
+            let entries =
+                fs::read_dir(&directory).map_err(|error| MigrationsError::CannotReadDirectory {
+                    path: directory.display().to_string(),
+                    error,
+                })?;
+            for entry in entries {
+                let path = entry
+                    .map_err(|error| MigrationsError::CannotReadDirectory {
+                        path: directory.display().to_string(),
+                        error,
+                    })?
+                    .path();
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy())
+                    .unwrap_or_default();
+                if name.starts_with('.') {
+                    continue;
+                }
+                if path.is_dir() {
+                    directories.push(path);
+                    continue;
+                }
+                if !name.ends_with(".surql") && name != ARCHIVE_FILE_NAME {
+                    continue;
+                }
+                let relative = path.strip_prefix(&self.path).unwrap_or(&path);
+                file_names.push(
+                    relative
+                        .components()
+                        .map(|component| component.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                );
+            }
+        }
+        Ok(file_names)
+    }
+
+    fn load(&self, file_name: &str) -> Result<String, MigrationsError> {
+        fs::read_to_string(self.path.join(file_name)).map_err(|error| {
+            MigrationsError::CannotReadFile {
+                file_name: file_name.to_owned(),
+                error,
+            }
+        })
+    }
+}
+
+/// Files held in memory, e.g. for tests.
+/// ```ignore
+/// MemorySource::new([("0001_create_user.surql", "DEFINE TABLE user SCHEMAFULL;")])
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    files: Vec<(String, String)>,
+}
+
+impl MemorySource {
+    pub fn new<N, S>(files: impl IntoIterator<Item = (N, S)>) -> Self
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        Self {
+            files: files
+                .into_iter()
+                .map(|(file_name, sql)| (file_name.into(), sql.into()))
+                .collect(),
+        }
+    }
+
+    /// Adds a file.
+    pub fn file(mut self, file_name: impl Into<String>, sql: impl Into<String>) -> Self {
+        self.files.push((file_name.into(), sql.into()));
+        self
+    }
+}
+
+impl MigrationSource for MemorySource {
+    fn file_names(&self) -> Result<Vec<String>, MigrationsError> {
+        Ok(self
+            .files
+            .iter()
+            .map(|(file_name, _)| file_name.clone())
+            .collect())
+    }
+
+    fn load(&self, file_name: &str) -> Result<String, MigrationsError> {
+        self.files
+            .iter()
+            .find(|(name, _)| name == file_name)
+            .map(|(_, sql)| sql.clone())
+            .ok_or_else(|| MigrationsError::CannotLoadFile {
+                file_name: file_name.to_owned(),
+            })
+    }
+}
@@ -0,0 +1,318 @@
+//! A minimal SurrealQL scanner that tells code apart from strings and comments.
+
+/// A piece of SurrealQL source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    /// Anything that is not a quoted string or a comment.
+    Code(&'a str),
+    /// A quoted string or escaped identifier, including its quotes.
+    Quoted(&'a str),
+    /// A `--`, `//`, `#` or `/* */` comment.
+    Comment(&'a str),
+}
+
+impl<'a> Token<'a> {
+    pub(crate) fn text(&self) -> &'a str {
+        match self {
+            Token::Code(text) | Token::Quoted(text) | Token::Comment(text) => text,
+        }
+    }
+}
+
+enum TokenKind {
+    Quoted,
+    Comment,
+}
+
+/// Splits `sql` into code, quoted and comment tokens. Unterminated strings and comments run to the end of `sql`.
+pub(crate) fn tokenize(sql: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.char_indices().peekable();
+    let mut code_start = 0;
+    while let Some((start, c)) = chars.next() {
+        let next = chars.peek().map(|(_, next)| *next);
+        let token_end = match (c, next) {
+            ('-', Some('-')) | ('/', Some('/')) | ('#', _) => {
+                let end = sql[start..].find('\n').map_or(sql.len(), |i| start + i);
+                Some((end, TokenKind::Comment))
+            }
+            ('/', Some('*')) => {
+                let end = sql[start + 2..]
+                    .find("*/")
+                    .map_or(sql.len(), |i| start + 2 + i + 2);
+                Some((end, TokenKind::Comment))
+            }
+            ('\'' | '"' | '`', _) => Some((quoted_end(sql, start, c, c), TokenKind::Quoted)),
+            ('⟨', _) => Some((quoted_end(sql, start, '⟨', '⟩'), TokenKind::Quoted)),
+            _ => None,
+        };
+        if let Some((end, kind)) = token_end {
+            if code_start < start {
+                tokens.push(Token::Code(&sql[code_start..start]));
+            }
+            let text = &sql[start..end];
+            tokens.push(match kind {
+                TokenKind::Quoted => Token::Quoted(text),
+                TokenKind::Comment => Token::Comment(text),
+            });
+            while chars.peek().is_some_and(|(index, _)| *index < end) {
+                chars.next();
+            }
+            code_start = end;
+        }
+    }
+    if code_start < sql.len() {
+        tokens.push(Token::Code(&sql[code_start..]));
+    }
+    tokens
+}
+
+/// Returns the index just past the `close` that ends the quoted token starting at `start`.
+fn quoted_end(sql: &str, start: usize, open: char, close: char) -> usize {
+    let body_start = start + open.len_utf8();
+    let mut escaped = false;
+    for (index, c) in sql[body_start..].char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == close {
+            return body_start + index + c.len_utf8();
+        }
+    }
+    sql.len()
+}
+
+/// Removes comments and whitespace from `sql`. A single space is kept between two words so that the result still
+/// parses the same way. Quoted strings are kept as is.
+pub(crate) fn normalize(sql: &str) -> String {
+    fn is_word(c: char) -> bool {
+        c.is_alphanumeric() || c == '_'
+    }
+
+    let mut normalized = String::with_capacity(sql.len());
+    let mut pending_space = false;
+    for token in tokenize(sql) {
+        match token {
+            Token::Comment(_) => pending_space = true,
+            Token::Quoted(text) => {
+                normalized.push_str(text);
+                pending_space = false;
+            }
+            Token::Code(text) => {
+                for c in text.chars() {
+                    if c.is_whitespace() {
+                        pending_space = true;
+                        continue;
+                    }
+                    if pending_space && is_word(c) && normalized.ends_with(is_word) {
+                        normalized.push(' ');
+                    }
+                    normalized.push(c);
+                    pending_space = false;
+                }
+            }
+        }
+    }
+    normalized
+}
+
+/// A statement found by [`statements`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Statement {
+    /// The byte offset the statement starts at.
+    pub(crate) start: usize,
+    /// The 1-based line the statement starts on.
+    pub(crate) line: usize,
+}
+
+/// Keywords of statements that do not produce a result in a query response.
+const STATEMENTS_WITHOUT_RESULT: [&str; 4] = ["BEGIN", "CANCEL", "COMMIT", "OPTION"];
+
+/// Splits `sql` into the statements that produce a result in a query response, in order. Statements are separated by
+/// `;` outside of strings, comments and blocks.
+pub(crate) fn statements(sql: &str) -> Vec<Statement> {
+    let mut statement_starts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut depth = 0usize;
+    let mut offset = 0;
+    for token in tokenize(sql) {
+        match token {
+            Token::Comment(_) => {}
+            Token::Quoted(_) => {
+                start.get_or_insert(offset);
+            }
+            Token::Code(text) => {
+                for (index, c) in text.char_indices() {
+                    match c {
+                        '{' | '(' | '[' => depth += 1,
+                        '}' | ')' | ']' => depth = depth.saturating_sub(1),
+                        ';' if depth == 0 => {
+                            statement_starts.extend(start.take());
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    if !c.is_whitespace() {
+                        start.get_or_insert(offset + index);
+                    }
+                }
+            }
+        }
+        offset += token.text().len();
+    }
+    statement_starts.extend(start);
+
+    statement_starts
+        .into_iter()
+        .filter(|start| {
+            let keyword: String = sql[*start..]
+                .chars()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .collect();
+            !STATEMENTS_WITHOUT_RESULT.contains(&keyword.to_ascii_uppercase().as_str())
+        })
+        .map(|start| Statement {
+            start,
+            line: sql[..start].matches('\n').count() + 1,
+        })
+        .collect()
+}
+
+/// Rewrites `DEFINE` statements to `DEFINE ... OVERWRITE` and `REMOVE` statements to `REMOVE ... IF EXISTS`, unless
+/// they already have one of these clauses, so that running `sql` again does not fail.
+#[cfg(feature = "kv-mem")]
+pub(crate) fn idempotent(sql: &str) -> String {
+    let mut rewritten = String::with_capacity(sql.len());
+    let mut copied = 0;
+    for statement in statements(sql) {
+        let mut words = sql[statement.start..]
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .filter(|word| !word.is_empty());
+        let (Some(keyword), Some(kind), next) = (words.next(), words.next(), words.next()) else {
+            continue;
+        };
+        let next = next.unwrap_or_default();
+        let insert = if keyword.eq_ignore_ascii_case("DEFINE")
+            && !next.eq_ignore_ascii_case("OVERWRITE")
+            && !next.eq_ignore_ascii_case("IF")
+        {
+            " OVERWRITE"
+        } else if keyword.eq_ignore_ascii_case("REMOVE") && !next.eq_ignore_ascii_case("IF") {
+            " IF EXISTS"
+        } else {
+            continue;
+        };
+        let kind_end = statement.start
+            + sql[statement.start..]
+                .find(kind)
+                .expect("the kind was split from this statement")
+            + kind.len();
+        rewritten.push_str(&sql[copied..kind_end]);
+        rewritten.push_str(insert);
+        copied = kind_end;
+    }
+    rewritten.push_str(&sql[copied..]);
+    rewritten
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The line and first word of each statement of `sql`.
+    fn starts(sql: &str) -> Vec<(usize, &str)> {
+        statements(sql)
+            .into_iter()
+            .map(|statement| {
+                let word = sql[statement.start..]
+                    .split(|c: char| !c.is_ascii_alphanumeric())
+                    .next()
+                    .unwrap_or_default();
+                (statement.line, word)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokenizes_comments_and_quotes() {
+        assert_eq!(
+            tokenize("SELECT 1; -- a;b\n'x;y' /* ; */"),
+            vec![
+                Token::Code("SELECT 1; "),
+                Token::Comment("-- a;b"),
+                Token::Code("\n"),
+                Token::Quoted("'x;y'"),
+                Token::Code(" "),
+                Token::Comment("/* ; */"),
+            ]
+        );
+        assert_eq!(
+            tokenize(r#""a\"b" `c`"#),
+            vec![
+                Token::Quoted(r#""a\"b""#),
+                Token::Code(" "),
+                Token::Quoted("`c`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_statements_outside_of_comments_and_quotes() {
+        let sql = "DEFINE TABLE a; -- one; two\nCREATE a SET b = 'x;y';\n/* ; */ SELECT * FROM a";
+        assert_eq!(starts(sql), [(1, "DEFINE"), (2, "CREATE"), (3, "SELECT")]);
+    }
+
+    #[test]
+    fn keeps_blocks_in_one_statement() {
+        let sql = "DEFINE FUNCTION fn::a() { LET $b = 1; RETURN $b; };\nDEFINE EVENT e ON t WHEN true THEN { CREATE log; };";
+        assert_eq!(starts(sql), [(1, "DEFINE"), (2, "DEFINE")]);
+    }
+
+    #[test]
+    fn skips_transaction_statements() {
+        assert_eq!(
+            starts("BEGIN TRANSACTION;\nCREATE a;\nCOMMIT TRANSACTION;"),
+            [(2, "CREATE")]
+        );
+        assert_eq!(starts("begin;\ncreate a;\ncommit;"), [(2, "create")]);
+    }
+
+    #[test]
+    fn normalizes_whitespace_and_comments() {
+        assert_eq!(
+            normalize("DEFINE   TABLE a; -- comment\n\nDEFINE FIELD b ON a TYPE string;"),
+            "DEFINE TABLE a;DEFINE FIELD b ON a TYPE string;"
+        );
+        assert_eq!(
+            normalize("CREATE a SET b = '  x  ';"),
+            "CREATE a SET b='  x  ';"
+        );
+    }
+
+    #[cfg(feature = "kv-mem")]
+    #[test]
+    fn makes_definitions_idempotent() {
+        assert_eq!(
+            idempotent("DEFINE TABLE a;\nREMOVE FIELD b ON a;"),
+            "DEFINE TABLE OVERWRITE a;\nREMOVE FIELD IF EXISTS b ON a;"
+        );
+        assert_eq!(
+            idempotent("define table a; remove index i on a;"),
+            "define table OVERWRITE a; remove index IF EXISTS i on a;"
+        );
+        assert_eq!(
+            idempotent("BEGIN; DEFINE TABLE a; COMMIT;"),
+            "BEGIN; DEFINE TABLE OVERWRITE a; COMMIT;"
+        );
+    }
+
+    #[cfg(feature = "kv-mem")]
+    #[test]
+    fn keeps_existing_clauses_comments_and_strings() {
+        let sql = "DEFINE TABLE OVERWRITE a; DEFINE FIELD IF NOT EXISTS b ON a; REMOVE TABLE IF EXISTS c;";
+        assert_eq!(idempotent(sql), sql);
+        let sql = "-- DEFINE TABLE x;\nCREATE a SET b = 'DEFINE TABLE y';";
+        assert_eq!(idempotent(sql), sql);
+    }
+}
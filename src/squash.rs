@@ -0,0 +1,187 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::MigrationsError,
+    files::{file_number, load_sql_files, SqlFile},
+    source::{DirectorySource, MigrationSource},
+};
+
+/// The file in the migrations directory that lists the migrations folded into the schema by [`squash`], one file
+/// name per line. Lines starting with `#` are comments.
+pub const ARCHIVE_FILE_NAME: &str = "archived.txt";
+
+const ARCHIVE_HEADER: &str = "\
+# Migrations squashed into the schema files. Do not edit, the migration engine reads this file to accept
+# the migrations table rows of these migrations, whose files no longer exist.
+";
+
+/// A migration listed in the archive, see [`ARCHIVE_FILE_NAME`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ArchivedMigration {
+    pub(crate) number: u32,
+    pub(crate) file_name: String,
+}
+
+/// Loads the archive of `source`, ordered by number. Empty if the source has no archive.
+pub(crate) fn load_archive(
+    source: &dyn MigrationSource,
+) -> Result<Vec<ArchivedMigration>, MigrationsError> {
+    if !source
+        .file_names()?
+        .iter()
+        .any(|file_name| file_name == ARCHIVE_FILE_NAME)
+    {
+        return Ok(Vec::new());
+    }
+    let mut archived = source
+        .load(ARCHIVE_FILE_NAME)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|file_name| {
+            Ok(ArchivedMigration {
+                number: file_number(file_name)?,
+                file_name: file_name.to_owned(),
+            })
+        })
+        .collect::<Result<Vec<_>, MigrationsError>>()?;
+    archived.sort_by_key(|archived| archived.number);
+    Ok(archived)
+}
+
+/// The result of [`squash`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Squash {
+    /// The consolidated schema file that replaced the schema files.
+    pub schema_path: PathBuf,
+    /// The names of the migration files that were folded into the schema and removed.
+    pub archived: Vec<String>,
+}
+
+/// Consolidates the schema files into a single schema file and removes every migration numbered up to `through`,
+/// including their down files. The schema files already contain every migration, so only they make up the new file.
+///
+/// The removed migrations are listed in the archive file of the migrations directory, see [`ARCHIVE_FILE_NAME`], so
+/// databases that already ran them keep passing validation, while new databases get them through the schema.
+/// Databases that recorded earlier migrations but not a squashed one fail with
+/// [`MigrationsError::ArchivedMigrationNotApplied`]. The remaining migrations keep their numbers. Migrations with an
+/// `@env` directive cannot be squashed, since the schema runs in every environment.
+pub fn squash(
+    migrations_directory: impl AsRef<Path>,
+    schema_directory: impl AsRef<Path>,
+    through: u32,
+) -> Result<Squash, MigrationsError> {
+    let migrations_directory = migrations_directory.as_ref();
+    let schema_directory = schema_directory.as_ref();
+    let migration_source = DirectorySource::new(migrations_directory);
+    let schema_source = DirectorySource::new(schema_directory);
+
+    let mut archived = load_archive(&migration_source)?;
+    let squashed: Vec<SqlFile> = load_sql_files(&migration_source)?
+        .into_iter()
+        .filter(|migration| migration.number <= through)
+        .collect();
+    if squashed.is_empty() {
+        return Err(MigrationsError::NothingToSquash { through });
+    }
+    if let Some(migration) = squashed
+        .iter()
+        .find(|migration| migration.directives.env.is_some())
+    {
+        return Err(MigrationsError::CannotSquashEnvMigration {
+            file_name: migration.file_name.clone(),
+        });
+    }
+    let schemas = load_sql_files(&schema_source)?;
+
+    // The schema files already contain every migration, including the ones after `through`, so folding the squashed
+    // migrations in again would run their older definitions after the newer ones of the schema files.
+    let mut consolidated = format!(
+        "-- Consolidated schema, squashed from the schema files when the migrations up to {} were archived.\n",
+        through
+    );
+    for schema in schemas.iter() {
+        consolidated.push_str(&format!(
+            "\n-- From schema file '{}'\n{}\n",
+            schema.file_name,
+            schema.sql.trim_end()
+        ));
+    }
+    let width = schemas
+        .first()
+        .map(|schema| {
+            schema
+                .file_name
+                .rsplit('/')
+                .next()
+                .unwrap_or(&schema.file_name)
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .count()
+        })
+        .unwrap_or(4);
+    let schema_path = schema_directory.join(format!("{:0width$}_schema.surql", 1));
+
+    // Written under a hidden name first, which sources skip, so the schema files are only removed once the
+    // consolidated file is complete.
+    let temporary_path = schema_directory.join(format!(".{:0width$}_schema.surql.tmp", 1));
+    fs::write(&temporary_path, consolidated).map_err(|error| MigrationsError::CannotWriteFile {
+        file_name: temporary_path.display().to_string(),
+        error,
+    })?;
+    fs::rename(&temporary_path, &schema_path).map_err(|error| {
+        MigrationsError::CannotWriteFile {
+            file_name: schema_path.display().to_string(),
+            error,
+        }
+    })?;
+    for schema in schemas.iter() {
+        // A previous squash already wrote the consolidated file, which the rename replaced.
+        if schema_directory.join(&schema.file_name) != schema_path {
+            remove_file(schema_directory, schema)?;
+        }
+    }
+
+    archived.extend(squashed.iter().map(|migration| ArchivedMigration {
+        number: migration.number,
+        file_name: migration.file_name.clone(),
+    }));
+    let mut archive = ARCHIVE_HEADER.to_owned();
+    for archived in archived.iter() {
+        archive.push_str(&archived.file_name);
+        archive.push('\n');
+    }
+    let archive_path = migrations_directory.join(ARCHIVE_FILE_NAME);
+    fs::write(&archive_path, archive).map_err(|error| MigrationsError::CannotWriteFile {
+        file_name: archive_path.display().to_string(),
+        error,
+    })?;
+    for migration in squashed.iter() {
+        remove_file(migrations_directory, migration)?;
+    }
+
+    Ok(Squash {
+        schema_path,
+        archived: squashed
+            .into_iter()
+            .map(|migration| migration.file_name)
+            .collect(),
+    })
+}
+
+/// Removes `file` and its down file from `directory`.
+fn remove_file(directory: &Path, file: &SqlFile) -> Result<(), MigrationsError> {
+    let file_names = std::iter::once(file.file_name.as_str())
+        .chain(file.down.iter().map(|down| down.file_name.as_str()));
+    for file_name in file_names {
+        let path = directory.join(file_name);
+        fs::remove_file(&path).map_err(|error| MigrationsError::CannotWriteFile {
+            file_name: path.display().to_string(),
+            error,
+        })?;
+    }
+    Ok(())
+}
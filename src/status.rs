@@ -0,0 +1,165 @@
+use std::fmt;
+
+use surrealdb::{Connection, Surreal};
+
+use crate::{
+    engine::MigrationEngine,
+    errors::MigrationsError,
+    history::{self, HistoryStatus, Migration, REPEATABLE_NUMBER},
+};
+
+/// Whether a migration has ran.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationState {
+    /// Recorded in the migrations table. `date_ran` is `None` if the migration was recorded when the schema was created.
+    Applied {
+        date_ran: Option<surrealdb::sql::Datetime>,
+    },
+    /// Recorded in the migrations table as [`crate::HistoryStatus::Failed`], so it may be partially applied.
+    Failed {
+        date_ran: Option<surrealdb::sql::Datetime>,
+    },
+    /// Has a migration file but is not recorded in the migrations table.
+    Pending,
+    /// Recorded in the migrations table and squashed into the schema files, see [`crate::squash`].
+    Archived {
+        date_ran: Option<surrealdb::sql::Datetime>,
+    },
+    /// Recorded in the migrations table but has no migration file.
+    Unknown,
+}
+
+/// The state of a single migration, see [`MigrationEngine::status`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationStatus {
+    pub number: u32,
+    /// The name of the migration file, or the name recorded in the migrations table if the file no longer exists.
+    pub file_name: String,
+    pub state: MigrationState,
+}
+
+impl fmt::Display for MigrationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationState::Applied {
+                date_ran: Some(date_ran),
+            } => write!(f, "applied {}", date_ran.0.format("%Y-%m-%d %H:%M:%S UTC")),
+            MigrationState::Applied { date_ran: None } => write!(f, "applied with schema"),
+            MigrationState::Archived { .. } => write!(f, "archived"),
+            MigrationState::Failed { .. } => write!(f, "failed"),
+            MigrationState::Pending => write!(f, "pending"),
+            MigrationState::Unknown => write!(f, "unknown (no file)"),
+        }
+    }
+}
+
+impl From<Migration> for MigrationState {
+    /// The state of a migration recorded in the migrations table.
+    fn from(db_migration: Migration) -> Self {
+        match db_migration.status {
+            HistoryStatus::Applied => MigrationState::Applied {
+                date_ran: db_migration.date_ran,
+            },
+            HistoryStatus::Failed => MigrationState::Failed {
+                date_ran: db_migration.date_ran,
+            },
+        }
+    }
+}
+
+impl fmt::Display for MigrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>4}  {:<50}  {}",
+            self.number, self.file_name, self.state
+        )
+    }
+}
+
+impl<C: Connection> MigrationEngine<C> {
+    /// Lists every migration, from either the migration files or the migrations table, ordered by number, followed by
+    /// the repeatable migrations ordered by file name. A repeatable migration that changed since it last ran is
+    /// pending.
+    /// If the migrations table does not exist, every migration file is pending. Pending migrations that do not run in
+    /// the engine's environment are left out.
+    pub async fn status(
+        &self,
+        client: &Surreal<C>,
+    ) -> Result<Vec<MigrationStatus>, MigrationsError> {
+        let file_migrations = self.migration_files()?;
+        let mut db_migrations = if history::table_exists(client, &self.migrations_table).await? {
+            history::select_all(client, &self.migrations_table).await?
+        } else {
+            Vec::new()
+        };
+
+        let mut statuses: Vec<MigrationStatus> = file_migrations
+            .into_iter()
+            .filter_map(|migration_file| {
+                let state = match db_migrations
+                    .iter()
+                    .position(|db_migration| db_migration.number == migration_file.number)
+                {
+                    Some(index) => db_migrations.remove(index).into(),
+                    None if !self.runs_in_environment(&migration_file) => return None,
+                    None => MigrationState::Pending,
+                };
+                Some(MigrationStatus {
+                    number: migration_file.number,
+                    file_name: migration_file.file_name,
+                    state,
+                })
+            })
+            .collect();
+
+        let pending_repeatables = self.pending_repeatables(&db_migrations)?;
+        for repeatable in self.repeatable_files()? {
+            let db_migration = db_migrations
+                .iter()
+                .position(|db_migration| {
+                    db_migration.number == REPEATABLE_NUMBER
+                        && db_migration.file_name == repeatable.file_name
+                })
+                .map(|index| db_migrations.remove(index));
+            let state = match db_migration {
+                Some(db_migration)
+                    if db_migration.status == HistoryStatus::Failed
+                        || !pending_repeatables.contains(&repeatable) =>
+                {
+                    db_migration.into()
+                }
+                _ if !self.runs_in_environment(&repeatable) => continue,
+                _ => MigrationState::Pending,
+            };
+            statuses.push(MigrationStatus {
+                number: repeatable.number,
+                file_name: repeatable.file_name,
+                state,
+            });
+        }
+
+        let archived = self.archived_migrations()?;
+        statuses.extend(db_migrations.into_iter().map(|db_migration| {
+            let state = if archived.iter().any(|archived| {
+                archived.number == db_migration.number
+                    && archived.file_name == db_migration.file_name
+            }) {
+                MigrationState::Archived {
+                    date_ran: db_migration.date_ran,
+                }
+            } else {
+                MigrationState::Unknown
+            };
+            MigrationStatus {
+                number: db_migration.number,
+                file_name: db_migration.file_name,
+                state,
+            }
+        }));
+        // Repeatable migrations run after the versioned ones.
+        statuses.sort_by_key(|status| (status.number == REPEATABLE_NUMBER, status.number));
+
+        Ok(statuses)
+    }
+}
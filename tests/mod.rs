@@ -1,5 +1,21 @@
-use surrealdb::{engine::remote::ws::{Client, Ws}, opt::auth::Root, Surreal};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
+use serde::Deserialize;
+use surrealdb::{
+    engine::local::{Db, Mem},
+    Surreal,
+};
+use surrealdb_migration_engine::{
+    new_migration, squash, BoxError, DatabaseSchema, DirectorySource, EmbeddedSource, HookContext,
+    HookEvent, LockPolicy, MemorySource, MigrationEngine, MigrationKind, MigrationState,
+    MigrationTarget, MigrationsError, NewMigrationOptions, PlanAction, RepairOperation,
+    TableSchema, TransactionMode, ValidationPolicy, REPEATABLE_NUMBER,
+};
+#[cfg(feature = "kv-mem")]
+use surrealdb_migration_engine::{GenerateTarget, SchemaChange, SchemaItemKind};
 
 #[derive(rust_embed::RustEmbed)]
 #[folder = "tests/migrations"]
@@ -9,24 +25,1247 @@ struct MigrationFiles;
 #[folder = "tests/schema"]
 struct SchemaFiles;
 
-/// Start the server with the following command:
-/// ```bash
-/// podman run -u root --rm -p 8000:8000 -v ./surrealdb/data:/surrealdb/data surrealdb/surrealdb:v1.1.1 start --auth --user root --pass root file:/surrealdb/data/mydatabase.db
-/// ```
-/// Connect to the server with the following command:
-/// ```bash
-///  podman run -it --rm --network=host surrealdb/surrealdb:v1.1.1 sql --endpoint ws://0.0.0.0:8000 -u root -p root
-/// ```
+#[derive(rust_embed::RustEmbed)]
+#[folder = "tests/failing_migrations"]
+struct FailingMigrationFiles;
+
+#[derive(rust_embed::RustEmbed)]
+#[folder = "tests/env_migrations"]
+struct EnvMigrationFiles;
+
+#[derive(rust_embed::RustEmbed)]
+#[folder = "tests/directive_migrations"]
+struct DirectiveMigrationFiles;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MigrationRow {
+    file_name: String,
+    number: u32,
+    date_ran: Option<surrealdb::sql::Datetime>,
+    status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TestRecord {
+    string: String,
+}
+
+/// Creates a fresh in-process database using the `kv-mem` engine.
+async fn client() -> Surreal<Db> {
+    let _ = tracing_subscriber::fmt::try_init();
+    let client = Surreal::new::<Mem>(()).await.unwrap();
+    client.use_ns("system").use_db("system").await.unwrap();
+    client
+}
+
+async fn migration_rows(client: &Surreal<Db>) -> Vec<MigrationRow> {
+    client
+        .query("SELECT * FROM migrations ORDER BY number;")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap()
+}
+
 #[tokio::test]
 async fn create_migration_table_if_not_exists() {
-    tracing_subscriber::fmt::init();
-    let client: Surreal<Client> = Surreal::new::<Ws>("127.0.0.1:8000").await.unwrap();
-    client.signin(Root {
-        username: "root",
-        password: "root",
+    let client = client().await;
+
+    surrealdb_migration_engine::run::<MigrationFiles, SchemaFiles>(&client)
+        .await
+        .unwrap();
+
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].number, 1);
+    assert_eq!(rows[0].file_name, "0001_add_number_field_to_test.surql");
+    assert!(rows[0].date_ran.is_none());
+}
+
+#[tokio::test]
+async fn run_is_idempotent() {
+    let client = client().await;
+
+    surrealdb_migration_engine::run::<MigrationFiles, SchemaFiles>(&client)
+        .await
+        .unwrap();
+    surrealdb_migration_engine::run::<MigrationFiles, SchemaFiles>(&client)
+        .await
+        .unwrap();
+
+    assert_eq!(migration_rows(&client).await.len(), 1);
+}
+
+/// Sets up the database as it was before migration `0001` with an empty `migrations` table.
+async fn create_database_before_migrations(client: &Surreal<Db>) {
+    client
+        .query(
+            r#"
+DEFINE TABLE test SCHEMAFULL;
+DEFINE FIELD string ON TABLE test TYPE string;
+
+DEFINE TABLE migrations SCHEMAFULL;
+DEFINE FIELD fileName ON TABLE migrations TYPE string;
+DEFINE FIELD number ON TABLE migrations TYPE int;
+DEFINE FIELD dateRan ON TABLE migrations TYPE option<datetime>;
+"#,
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn runs_migrations_missing_from_table() {
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+
+    surrealdb_migration_engine::run::<MigrationFiles, SchemaFiles>(&client)
+        .await
+        .unwrap();
+
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].date_ran.is_some());
+}
+
+#[tokio::test]
+async fn custom_migrations_table() {
+    let client = client().await;
+    let engine = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>()
+        .migrations_table("schema_history");
+
+    engine.run(&client).await.unwrap();
+    engine.verify(&client).await.unwrap();
+
+    let rows: Vec<MigrationRow> = client
+        .query("SELECT * FROM schema_history;")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert!(migration_rows(&client).await.is_empty());
+}
+
+#[tokio::test]
+async fn plan_does_not_touch_database() {
+    let client = client().await;
+    let engine = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>();
+
+    let plan = engine.plan(&client).await.unwrap();
+    assert_eq!(plan.action, PlanAction::CreateSchema);
+    assert_eq!(plan.files.len(), 1);
+    assert_eq!(plan.history.len(), 1);
+    assert!(plan.sql().contains("DEFINE TABLE test SCHEMAFULL;"));
+    assert!(migration_rows(&client).await.is_empty());
+
+    engine.run(&client).await.unwrap();
+    let plan = engine.plan(&client).await.unwrap();
+    assert_eq!(plan.action, PlanAction::UpToDate);
+    assert!(plan.is_empty());
+}
+
+/// Sets up the database as it is after migration `0001`, without a `migrations` table.
+async fn create_database_without_migrations_table(client: &Surreal<Db>) {
+    client
+        .query(
+            r#"
+DEFINE TABLE test SCHEMAFULL;
+DEFINE FIELD string ON TABLE test TYPE string;
+DEFINE FIELD number ON TABLE test TYPE int;
+"#,
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn refuses_to_create_schema_in_non_empty_database() {
+    let client = client().await;
+    client
+        .query("DEFINE TABLE legacy SCHEMALESS;")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    let engine = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>();
+
+    let error = engine.run(&client).await.unwrap_err();
+    assert!(
+        matches!(&error, MigrationsError::DatabaseNotEmpty { found, .. } if found == "table legacy"),
+        "{error}"
+    );
+    assert!(migration_rows(&client).await.is_empty());
+
+    engine
+        .validation(ValidationPolicy {
+            allow_non_empty_database: true,
+            ..ValidationPolicy::default()
+        })
+        .run(&client)
+        .await
+        .unwrap();
+    assert_eq!(migration_rows(&client).await.len(), 1);
+}
+
+#[tokio::test]
+async fn baseline_records_migrations_without_running_them() {
+    let client = client().await;
+    create_database_without_migrations_table(&client).await;
+    let engine = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>();
+
+    let baselined = engine.baseline(&client, 1).await.unwrap();
+    assert_eq!(baselined.len(), 1);
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].file_name, "0001_add_number_field_to_test.surql");
+    assert!(rows[0].date_ran.is_none());
+
+    assert!(engine.plan(&client).await.unwrap().is_empty());
+    engine.verify(&client).await.unwrap();
+    assert!(matches!(
+        engine.baseline(&client, 1).await,
+        Err(MigrationsError::MigrationsTableExists { .. })
+    ));
+}
+
+/// An engine with `migrations` migrations that each add a field, and a repeatable migration that changes with them.
+fn targeted_engine(migrations: usize) -> MigrationEngine<Db> {
+    let source = (1..=migrations).fold(
+        MemorySource::default().file(
+            "R_fields.surql",
+            format!("DEFINE PARAM OVERWRITE $fields VALUE {migrations};"),
+        ),
+        |source, number| {
+            source.file(
+                format!("000{number}_field_{number}.surql"),
+                format!("DEFINE FIELD field_{number} ON TABLE person TYPE option<int>;"),
+            )
+        },
+    );
+    MigrationEngine::new(
+        source,
+        MemorySource::default().file(
+            "0001_person.surql",
+            "DEFINE TABLE person SCHEMAFULL; DEFINE FIELD field_1 ON TABLE person TYPE option<int>;",
+        ),
+    )
+}
+
+#[tokio::test]
+async fn migrates_to_target() {
+    let client = client().await;
+    assert!(matches!(
+        targeted_engine(3).migrate_to(&client, 1).await,
+        Err(MigrationsError::TargetBeforeSchema { target: 1, .. })
+    ));
+    targeted_engine(1).run(&client).await.unwrap();
+    let engine = targeted_engine(3);
+
+    let plan = engine.migrate_to(&client, 2).await.unwrap();
+    assert_eq!(plan.action, PlanAction::ApplyMigrations);
+    assert_eq!(plan.files.len(), 1);
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[2].file_name, "0002_field_2.surql");
+
+    let plan = engine
+        .plan_to(&client, MigrationTarget::Steps(1))
+        .await
+        .unwrap();
+    let files: Vec<&str> = plan.files.iter().map(|f| f.file_name.as_str()).collect();
+    assert_eq!(files, ["0003_field_3.surql", "R_fields.surql"]);
+    engine
+        .migrate_to(&client, MigrationTarget::Steps(1))
+        .await
+        .unwrap();
+    assert!(engine.plan(&client).await.unwrap().is_empty());
+}
+
+/// Adds hooks to `engine` that record each event and its files in `events`.
+fn recording_hooks(
+    mut engine: MigrationEngine<Db>,
+    events: &Arc<Mutex<Vec<String>>>,
+) -> MigrationEngine<Db> {
+    for event in [
+        HookEvent::BeforeRun,
+        HookEvent::AfterSchema,
+        HookEvent::BeforeMigration,
+        HookEvent::AfterMigration,
+        HookEvent::Failure,
+        HookEvent::AfterRun,
+    ] {
+        let events = events.clone();
+        engine = engine.hook(event, move |context: HookContext, _db: Surreal<Db>| {
+            let events = events.clone();
+            async move {
+                let files: Vec<&str> = context.files.iter().map(|f| f.file_name.as_str()).collect();
+                events
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}: {}", context.event, files.join(", ")));
+                Ok::<_, BoxError>(())
+            }
+        });
+    }
+    engine
+}
+
+#[tokio::test]
+async fn calls_hooks_around_schema_and_migrations() {
+    let client = client().await;
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    recording_hooks(targeted_engine(1), &events)
+        .run(&client)
+        .await
+        .unwrap();
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "before run: 0001_person.surql, R_fields.surql",
+            "after schema: 0001_person.surql",
+            "before migration: R_fields.surql",
+            "after migration: R_fields.surql",
+            "after run: 0001_person.surql, R_fields.surql",
+        ]
+    );
+
+    events.lock().unwrap().clear();
+    let error = recording_hooks(targeted_engine(2), &events)
+        .hook(HookEvent::BeforeMigration, |_context, _db| async {
+            Err::<(), BoxError>("not now".into())
+        })
+        .run(&client)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        MigrationsError::HookFailed {
+            event: HookEvent::BeforeMigration,
+            ..
+        }
+    ));
+    assert_eq!(
+        events.lock().unwrap().last().unwrap(),
+        "failure: 0002_field_2.surql, R_fields.surql"
+    );
+    assert_eq!(migration_rows(&client).await.len(), 2);
+
+    events.lock().unwrap().clear();
+    let error = recording_hooks(targeted_engine(3), &events)
+        .migrate_to(&client().await, 1)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, MigrationsError::TargetBeforeSchema { .. }));
+    assert_eq!(*events.lock().unwrap(), ["failure: "]);
+}
+
+#[tokio::test]
+async fn status_lists_applied_and_pending() {
+    let client = client().await;
+    let engine = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>();
+
+    let status = engine.status(&client).await.unwrap();
+    assert_eq!(status.len(), 1);
+    assert_eq!(status[0].state, MigrationState::Pending);
+
+    engine.run(&client).await.unwrap();
+    client
+        .query("CREATE migrations SET fileName = '0002_removed.surql', number = 2;")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let status = engine.status(&client).await.unwrap();
+    assert_eq!(status.len(), 2);
+    assert_eq!(status[0].state, MigrationState::Applied { date_ran: None });
+    assert_eq!(status[1].file_name, "0002_removed.surql");
+    assert_eq!(status[1].state, MigrationState::Unknown);
+}
+
+#[tokio::test]
+async fn rollback_runs_down_files() {
+    let client = client().await;
+    let engine = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>();
+    engine.run(&client).await.unwrap();
+
+    let rolled_back = engine.rollback_last(&client).await.unwrap().unwrap();
+    assert_eq!(rolled_back.number, 1);
+    assert!(migration_rows(&client).await.is_empty());
+    assert!(engine.rollback_to(&client, 0).await.unwrap().is_empty());
+
+    engine.run(&client).await.unwrap();
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].date_ran.is_some());
+}
+
+#[tokio::test]
+async fn detects_changed_migration_files() {
+    let client = client().await;
+    let engine = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>();
+    engine.run(&client).await.unwrap();
+    engine.verify(&client).await.unwrap();
+
+    client
+        .query("UPDATE migrations SET checksum = 'sha256:0000';")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let error = engine.verify(&client).await.unwrap_err();
+    assert!(matches!(
+        error,
+        MigrationsError::MigrationFileChecksumMismatch { ref file_name, .. }
+            if file_name == "0001_add_number_field_to_test.surql"
+    ));
+    assert!(error
+        .to_string()
+        .contains("0001_add_number_field_to_test.surql"));
+}
+
+#[tokio::test]
+async fn reports_failing_file_and_statement() {
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+
+    let error = MigrationEngine::embedded::<FailingMigrationFiles, SchemaFiles>()
+        .run(&client)
+        .await
+        .unwrap_err();
+
+    match error {
+        MigrationsError::MigrationStatementFailed {
+            file_name,
+            statement,
+            line,
+            ..
+        } => {
+            assert_eq!(file_name, "0001_throw.surql");
+            assert_eq!(statement, 2);
+            assert_eq!(line, 3);
+        }
+        error => panic!("unexpected error: {error}"),
+    }
+    assert!(migration_rows(&client).await.is_empty());
+}
+
+#[tokio::test]
+async fn per_migration_transactions() {
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+
+    MigrationEngine::embedded::<MigrationFiles, SchemaFiles>()
+        .transaction_mode(TransactionMode::PerMigration)
+        .run(&client)
+        .await
+        .unwrap();
+
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].date_ran.is_some());
+}
+
+#[tokio::test]
+async fn failing_migration_without_transaction_is_recorded_as_failed() {
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+    let engine = MigrationEngine::embedded::<FailingMigrationFiles, SchemaFiles>()
+        .transaction_mode(TransactionMode::None);
+
+    let error = engine.run(&client).await.unwrap_err();
+
+    assert!(matches!(
+        error,
+        MigrationsError::MigrationStatementFailed { .. }
+    ));
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].status.as_deref(), Some("failed"));
+    let status = engine.status(&client).await.unwrap();
+    assert!(matches!(status[0].state, MigrationState::Failed { .. }));
+    assert!(matches!(
+        engine.run(&client).await,
+        Err(MigrationsError::MigrationFailedPreviously { .. })
+    ));
+
+    let plan = engine
+        .plan_repair(&client, RepairOperation::DeleteFailed)
+        .await
+        .unwrap();
+    assert_eq!(plan.changes.len(), 1);
+    assert!(plan.changes[0].after.is_none());
+    engine.repair(&client, &plan).await.unwrap();
+    assert!(migration_rows(&client).await.is_empty());
+}
+
+#[tokio::test]
+async fn reads_directives() {
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+
+    let plan = MigrationEngine::embedded::<DirectiveMigrationFiles, SchemaFiles>()
+        .plan(&client)
+        .await
+        .unwrap();
+
+    assert_eq!(plan.action, PlanAction::ApplyMigrations);
+    assert_eq!(
+        plan.files[0].directives.description.as_deref(),
+        Some("Adds the number field to the test table")
+    );
+
+    let error = MigrationEngine::new(
+        MemorySource::new([("0001_timeout.surql", "-- @timeout soon\nDEFINE TABLE a;")]),
+        MemorySource::default(),
+    )
+    .plan(&client)
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(&error, MigrationsError::InvalidDirective { directive, .. } if directive == "@timeout soon"),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn env_directive_limits_migrations_to_environments() {
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+
+    let prod = MigrationEngine::embedded::<EnvMigrationFiles, SchemaFiles>().environment("prod");
+    prod.run(&client).await.unwrap();
+    assert!(migration_rows(&client).await.is_empty());
+    assert!(prod.status(&client).await.unwrap().is_empty());
+
+    MigrationEngine::embedded::<EnvMigrationFiles, SchemaFiles>()
+        .environment("staging")
+        .run(&client)
+        .await
+        .unwrap();
+    assert_eq!(migration_rows(&client).await.len(), 1);
+}
+
+fn engine_with_rust_migration() -> MigrationEngine<Db> {
+    MigrationEngine::embedded::<MigrationFiles, SchemaFiles>().rust_migration(
+        2,
+        "0002_create_rust_record",
+        |db: Surreal<Db>| async move {
+            db.query("CREATE test:rust SET string = 'rust', number = 2;")
+                .await?
+                .check()?;
+            Ok::<_, BoxError>(())
+        },
+    )
+}
+
+#[tokio::test]
+async fn rejects_migrations_numbered_zero() {
+    let client = client().await;
+    let gaps = ValidationPolicy {
+        allow_gaps: true,
+        ..ValidationPolicy::default()
+    };
+    let file = MigrationEngine::new(
+        MemorySource::new([("0000_init.surql", "DEFINE TABLE init;")]),
+        MemorySource::default(),
+    )
+    .validation(gaps);
+    let rust = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>()
+        .validation(gaps)
+        .rust_migration(0, "0000_init", |_: Surreal<Db>| async move {
+            Ok::<_, BoxError>(())
+        });
+
+    for engine in [file, rust] {
+        let error = engine.run(&client).await.unwrap_err();
+        assert!(
+            matches!(
+                error,
+                MigrationsError::FileNumbering {
+                    expected: 1,
+                    actual: 0,
+                    ..
+                }
+            ),
+            "{error}"
+        );
+    }
+    assert!(migration_rows(&client).await.is_empty());
+}
+
+#[tokio::test]
+async fn runs_rust_migrations_in_order() {
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+    let engine = engine_with_rust_migration();
+
+    let plan = engine.plan(&client).await.unwrap();
+    assert_eq!(plan.files.len(), 2);
+    assert_eq!(plan.files[1].kind, MigrationKind::Rust);
+
+    engine.run(&client).await.unwrap();
+
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].file_name, "0002_create_rust_record");
+    let record: Option<TestRecord> = client.select(("test", "rust")).await.unwrap();
+    assert_eq!(record.unwrap().string, "rust");
+}
+
+#[tokio::test]
+async fn rust_migrations_are_recorded_with_schema() {
+    let client = client().await;
+    let engine = engine_with_rust_migration();
+
+    engine.run(&client).await.unwrap();
+
+    assert_eq!(migration_rows(&client).await.len(), 2);
+    let record: Option<TestRecord> = client.select(("test", "rust")).await.unwrap();
+    assert!(record.is_none());
+}
+
+#[tokio::test]
+async fn reads_files_from_directory() {
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+    let engine = MigrationEngine::new(
+        DirectorySource::new("tests/migrations"),
+        DirectorySource::new("tests/schema"),
+    );
+
+    engine.run(&client).await.unwrap();
+
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].file_name, "0001_add_number_field_to_test.surql");
+}
+
+#[tokio::test]
+async fn skips_hidden_and_other_files_in_directory() {
+    let directory = copy_fixtures("hidden_files");
+    let migrations = directory.join("migrations");
+    std::fs::write(migrations.join("README.md"), "Migrations").unwrap();
+    std::fs::write(migrations.join(".0002_swap.surql"), "INVALID").unwrap();
+    std::fs::create_dir_all(migrations.join("..data")).unwrap();
+    std::fs::write(migrations.join("..data/0002_linked.surql"), "INVALID").unwrap();
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+
+    directory_engine(&directory).run(&client).await.unwrap();
+
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].file_name, "0001_add_number_field_to_test.surql");
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn reads_files_from_memory() {
+    let client = client().await;
+    let engine = MigrationEngine::new(
+        MemorySource::new([(
+            "0001_add_name.surql",
+            "DEFINE FIELD name ON TABLE person TYPE string;",
+        )]),
+        MemorySource::default().file("0001_person.surql", "DEFINE TABLE person SCHEMAFULL;"),
+    );
+
+    engine.run(&client).await.unwrap();
+    client
+        .query("CREATE person SET name = 'Tobie';")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].file_name, "0001_add_name.surql");
+}
+
+fn locking_engine(wait: Duration) -> MigrationEngine<Db> {
+    MigrationEngine::embedded::<MigrationFiles, SchemaFiles>().lock(LockPolicy {
+        wait,
+        retry_interval: Duration::from_millis(10),
+        ..Default::default()
     })
-    .await.unwrap();
-    client.use_ns("system").use_db("system").await.unwrap();
+}
+
+#[tokio::test]
+async fn concurrent_runs_migrate_once() {
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+    let first = locking_engine(Duration::from_secs(10));
+    let second = locking_engine(Duration::from_secs(10));
+
+    let (first_result, second_result) = tokio::join!(first.run(&client), second.run(&client));
+    first_result.unwrap();
+    second_result.unwrap();
+
+    assert_eq!(migration_rows(&client).await.len(), 1);
+    let lock: Option<serde_json::Value> =
+        client.select(("migrations_lock", "global")).await.unwrap();
+    assert!(lock.is_none());
+}
+
+#[tokio::test]
+async fn waits_for_lock_held_by_another_process() {
+    let client = client().await;
+    client
+        .query("CREATE migrations_lock:global SET owner = 'other', expiresAt = time::now() + 1h;")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let error = locking_engine(Duration::from_millis(50))
+        .run(&client)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, MigrationsError::MigrationLocked { owner, .. } if owner == "other"));
+    assert!(migration_rows(&client).await.is_empty());
+}
+
+#[tokio::test]
+async fn breaks_stale_lock() {
+    let client = client().await;
+    client
+        .query("CREATE migrations_lock:global SET owner = 'crashed', expiresAt = time::now() - 1m;")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    locking_engine(Duration::ZERO).run(&client).await.unwrap();
+
+    assert_eq!(migration_rows(&client).await.len(), 1);
+}
+
+#[test]
+fn new_migration_takes_next_number() {
+    let directory = std::env::temp_dir().join(format!(
+        "surrealdb_migration_engine_new_migration_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("001_create_user.surql"), "").unwrap();
+    std::fs::write(directory.join("009_add_name.surql"), "").unwrap();
+
+    let created = new_migration(
+        &directory,
+        "Add age to user!",
+        NewMigrationOptions {
+            down: true,
+            header: true,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(created.number, 10);
+    assert_eq!(created.path, directory.join("010_add_age_to_user.surql"));
+    assert_eq!(
+        std::fs::read_to_string(&created.path).unwrap(),
+        "-- @description Add age to user!\n\n"
+    );
+    assert_eq!(
+        created.down_path,
+        Some(directory.join("010_add_age_to_user.down.surql"))
+    );
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+/// Copies the `tests/migrations` and `tests/schema` fixtures into a fresh directory named after `test`.
+fn copy_fixtures(test: &str) -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(format!(
+        "surrealdb_migration_engine_{}_{}",
+        test,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&directory);
+    for fixture in ["migrations", "schema"] {
+        std::fs::create_dir_all(directory.join(fixture)).unwrap();
+        for entry in std::fs::read_dir(std::path::Path::new("tests").join(fixture)).unwrap() {
+            let path = entry.unwrap().path();
+            std::fs::copy(
+                &path,
+                directory.join(fixture).join(path.file_name().unwrap()),
+            )
+            .unwrap();
+        }
+    }
+    directory
+}
+
+fn directory_engine(directory: &std::path::Path) -> MigrationEngine<Db> {
+    MigrationEngine::new(
+        DirectorySource::new(directory.join("migrations")),
+        DirectorySource::new(directory.join("schema")),
+    )
+}
+
+#[tokio::test]
+async fn squashed_migrations_are_archived() {
+    let directory = copy_fixtures("squash");
+    let existing = client().await;
+    create_database_before_migrations(&existing).await;
+    directory_engine(&directory).run(&existing).await.unwrap();
+
+    let squashed = squash(directory.join("migrations"), directory.join("schema"), 1).unwrap();
+
+    assert_eq!(
+        squashed.archived,
+        vec!["0001_add_number_field_to_test.surql"]
+    );
+    assert!(!directory
+        .join("migrations/0001_add_number_field_to_test.surql")
+        .exists());
+    let engine = directory_engine(&directory);
+    engine.run(&existing).await.unwrap();
+    engine.verify(&existing).await.unwrap();
+    let status = engine.status(&existing).await.unwrap();
+    assert!(matches!(status[0].state, MigrationState::Archived { .. }));
+
+    let fresh = client().await;
+    engine.run(&fresh).await.unwrap();
+    assert_eq!(migration_rows(&fresh).await.len(), 1);
+    fresh
+        .query("CREATE test SET string = 'squashed', number = 1;")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn squash_keeps_definitions_of_later_migrations() {
+    let directory = copy_fixtures("squash_later");
+    std::fs::write(
+        directory.join("migrations/0002_make_number_optional.surql"),
+        "DEFINE FIELD OVERWRITE number ON TABLE test TYPE option<int>;",
+    )
+    .unwrap();
+    std::fs::write(
+        directory.join("schema/0001_create_test_table.surql"),
+        "DEFINE TABLE test SCHEMAFULL;\n\nDEFINE FIELD string ON TABLE test TYPE string;\nDEFINE FIELD number ON TABLE test TYPE option<int>;",
+    )
+    .unwrap();
+
+    let squashed = squash(directory.join("migrations"), directory.join("schema"), 1).unwrap();
+
+    let schema = std::fs::read_to_string(&squashed.schema_path).unwrap();
+    assert!(schema.contains("TYPE option<int>"));
+    assert!(!schema.contains("TYPE int"));
+    let client = client().await;
+    directory_engine(&directory).run(&client).await.unwrap();
+    assert_eq!(migration_rows(&client).await.len(), 2);
+    client
+        .query("CREATE test SET string = 'no number';")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn refuses_squashed_migrations_a_database_did_not_run() {
+    let directory = copy_fixtures("squash_partial");
+    let partial = client().await;
+    create_database_before_migrations(&partial).await;
+    directory_engine(&directory).run(&partial).await.unwrap();
+    std::fs::write(
+        directory.join("migrations/0002_add_flag_to_test.surql"),
+        "DEFINE FIELD flag ON TABLE test TYPE option<bool>;",
+    )
+    .unwrap();
+
+    squash(directory.join("migrations"), directory.join("schema"), 2).unwrap();
+
+    let engine = directory_engine(&directory);
+    let error = engine.run(&partial).await.unwrap_err();
+    assert!(
+        matches!(&error, MigrationsError::ArchivedMigrationNotApplied { number: 2, file_name } if file_name == "0002_add_flag_to_test.surql"),
+        "{error}"
+    );
+    assert!(matches!(
+        engine.verify(&partial).await,
+        Err(MigrationsError::ArchivedMigrationNotApplied { number: 2, .. })
+    ));
+    assert_eq!(migration_rows(&partial).await.len(), 1);
+
+    let fresh = client().await;
+    engine.run(&fresh).await.unwrap();
+    assert_eq!(migration_rows(&fresh).await.len(), 2);
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[tokio::test]
+async fn repairs_migrations_table_of_older_versions() {
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+    client
+        .query(
+            r#"
+DEFINE FIELD number ON TABLE test TYPE int;
+CREATE migrations SET fileName = '0001_add_number_field_to_test.surql', number = 1, dateRan = time::now();
+"#,
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    let engine = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>();
+
+    let plan = engine
+        .plan_repair(&client, RepairOperation::RecomputeChecksums)
+        .await
+        .unwrap();
+    assert_eq!(plan.changes.len(), 1);
+    engine.repair(&client, &plan).await.unwrap();
+
+    let checksums: Vec<Option<String>> = client
+        .query("SELECT VALUE checksum FROM migrations;")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    assert!(matches!(checksums.as_slice(), [Some(_)]));
+    engine.verify(&client).await.unwrap();
+    assert!(engine.plan(&client).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn refuses_repairs_that_duplicate_rows() {
+    let client = client().await;
+    let engine = targeted_engine(2);
+    engine.run(&client).await.unwrap();
+
+    for operation in [
+        RepairOperation::Rename {
+            from: "0001_field_1.surql".to_owned(),
+            to: "0002_field_2.surql".to_owned(),
+        },
+        RepairOperation::Renumber { from: 1, to: 2 },
+    ] {
+        let error = engine.plan_repair(&client, operation).await.unwrap_err();
+        assert!(
+            matches!(&error, MigrationsError::RepairConflict { number: 2, file_name } if file_name == "0002_field_2.surql"),
+            "{error}"
+        );
+    }
+    engine.verify(&client).await.unwrap();
+}
+
+#[tokio::test]
+async fn repairs_renamed_and_edited_migrations() {
+    let directory = copy_fixtures("repair");
+    let client = client().await;
+    create_database_before_migrations(&client).await;
+    let engine = directory_engine(&directory);
+    engine.run(&client).await.unwrap();
+
+    let migrations = directory.join("migrations");
+    std::fs::remove_file(migrations.join("0001_add_number_field_to_test.surql")).unwrap();
+    std::fs::write(
+        migrations.join("0001_add_number.surql"),
+        "-- Adds the number field\nDEFINE FIELD number ON TABLE test TYPE int;",
+    )
+    .unwrap();
+    assert!(matches!(
+        engine.verify(&client).await,
+        Err(MigrationsError::MigrationFileDbMismatch { .. })
+    ));
+
+    let rename = RepairOperation::Rename {
+        from: "0001_add_number_field_to_test.surql".to_owned(),
+        to: "0001_add_number.surql".to_owned(),
+    };
+    let plan = engine.plan_repair(&client, rename).await.unwrap();
+    assert_eq!(plan.changes.len(), 1);
+    assert!(plan.changes[0]
+        .to_string()
+        .contains("fileName '0001_add_number_field_to_test.surql' -> '0001_add_number.surql'"));
+    engine.repair(&client, &plan).await.unwrap();
+    assert!(matches!(
+        engine.verify(&client).await,
+        Err(MigrationsError::MigrationFileChecksumMismatch { .. })
+    ));
+
+    let plan = engine
+        .plan_repair(&client, RepairOperation::RecomputeChecksums)
+        .await
+        .unwrap();
+    assert_eq!(plan.changes.len(), 1);
+    engine.repair(&client, &plan).await.unwrap();
+    engine.verify(&client).await.unwrap();
+    assert!(engine
+        .plan_repair(&client, RepairOperation::RecomputeChecksums)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let plan = engine
+        .plan_repair(&client, RepairOperation::Renumber { from: 1, to: 2 })
+        .await
+        .unwrap();
+    engine.repair(&client, &plan).await.unwrap();
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows[0].number, 2);
+    assert_eq!(rows[0].file_name, "0001_add_number.surql");
+}
+
+#[cfg(feature = "kv-mem")]
+#[tokio::test]
+async fn schema_is_equivalent_to_baseline_and_migrations() {
+    let engine = MigrationEngine::<Db>::embedded::<MigrationFiles, SchemaFiles>().baseline_schema(
+        MemorySource::default().file(
+            "0001_baseline.surql",
+            "DEFINE TABLE test SCHEMAFULL; DEFINE FIELD string ON TABLE test TYPE string;",
+        ),
+    );
+
+    assert!(engine.verify_schema_equivalence().await.unwrap().is_empty());
+}
+
+#[cfg(feature = "kv-mem")]
+#[tokio::test]
+async fn reports_schema_missing_from_migrations() {
+    let engine = MigrationEngine::<Db>::embedded::<MigrationFiles, SchemaFiles>();
+
+    let differences = engine.verify_schema_equivalence().await.unwrap();
+
+    let string_field = differences
+        .iter()
+        .find(|difference| {
+            difference.item.kind == SchemaItemKind::Field && difference.item.name == "string"
+        })
+        .unwrap();
+    assert!(matches!(string_field.change, SchemaChange::Extra { .. }));
+}
+
+#[cfg(feature = "kv-mem")]
+#[tokio::test]
+async fn detects_drift_from_schema() {
+    let client = client().await;
+    let engine = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>();
+    engine.run(&client).await.unwrap();
+    assert!(engine.detect_drift(&client).await.unwrap().is_empty());
+
+    client
+        .query("DEFINE FIELD hotfix ON TABLE test TYPE option<string>; DEFINE INDEX by_string ON TABLE test FIELDS string; REMOVE FIELD number ON TABLE test;")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let differences = engine.detect_drift(&client).await.unwrap();
+    let changes: Vec<(SchemaItemKind, &str, bool)> = differences
+        .iter()
+        .map(|difference| {
+            (
+                difference.item.kind,
+                difference.item.name.as_str(),
+                matches!(difference.change, SchemaChange::Extra { .. }),
+            )
+        })
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            (SchemaItemKind::Field, "hotfix", true),
+            (SchemaItemKind::Field, "number", false),
+            (SchemaItemKind::Index, "by_string", true),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn reads_database_schema() {
+    let client = client().await;
+    surrealdb_migration_engine::run::<MigrationFiles, SchemaFiles>(&client)
+        .await
+        .unwrap();
+
+    let schema = DatabaseSchema::read(&client).await.unwrap();
+
+    let test = &schema.tables["test"];
+    assert!(test.definition.starts_with("DEFINE TABLE test"));
+    assert_eq!(
+        test.fields.keys().collect::<Vec<_>>(),
+        vec!["number", "string"]
+    );
+    assert!(schema.tables.contains_key("migrations"));
+}
+
+#[test]
+fn parses_info_of_older_versions() {
+    let schema = DatabaseSchema::from_db_info(&serde_json::json!({
+        "tb": { "user": "DEFINE TABLE user SCHEMAFULL" },
+        "sc": { "account": "DEFINE SCOPE account" },
+        "fc": {},
+        "unknown": "ignored",
+    }))
+    .unwrap();
+    let table = TableSchema::from_table_info(
+        schema.tables["user"].definition.clone(),
+        &serde_json::json!({
+            "fd": { "name": "DEFINE FIELD name ON user TYPE string" },
+            "ix": {},
+            "ev": {},
+            "lv": {},
+        }),
+    )
+    .unwrap();
+
+    assert_eq!(
+        schema.accesses["account"],
+        "DEFINE SCOPE account".to_owned()
+    );
+    assert_eq!(table.fields.len(), 1);
+    assert!(DatabaseSchema::from_db_info(&serde_json::json!([])).is_err());
+    assert!(matches!(
+        DatabaseSchema::from_db_info(&serde_json::json!({ "fc": {} })),
+        Err(MigrationsError::UnexpectedInfo { .. })
+    ));
+    assert!(matches!(
+        DatabaseSchema::from_db_info(&serde_json::json!({ "tb": {}, "fc": "none" })),
+        Err(MigrationsError::UnexpectedInfo { reason, .. }) if reason == "`fc` is not an object"
+    ));
+}
+
+#[cfg(feature = "kv-mem")]
+#[tokio::test]
+async fn generated_migration_closes_drift() {
+    let client = client().await;
+    let engine = MigrationEngine::embedded::<MigrationFiles, SchemaFiles>();
+    engine.run(&client).await.unwrap();
+    client
+        .query("DEFINE FIELD hotfix ON TABLE test TYPE option<string>; REMOVE FIELD number ON TABLE test;")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    client
+        .query("DEFINE USER app ON DATABASE PASSWORD 'secret' ROLES EDITOR; DEFINE ACCESS api ON DATABASE TYPE JWT ALGORITHM HS512 KEY 'secret';")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let sql = engine
+        .migration_sql(GenerateTarget::Database(&client))
+        .await
+        .unwrap();
+
+    assert!(sql
+        .starts_with("REMOVE FIELD hotfix ON TABLE test;\nDEFINE FIELD OVERWRITE number ON test"));
+    assert!(!sql.contains("REMOVE USER") && !sql.contains("REMOVE ACCESS"));
+    client.query(sql).await.unwrap().check().unwrap();
+    assert!(engine.detect_drift(&client).await.unwrap().is_empty());
+}
+
+#[cfg(feature = "kv-mem")]
+#[tokio::test]
+async fn generates_migration_file_against_migrations() {
+    let directory = copy_fixtures("generate");
+    let engine = directory_engine(&directory);
+
+    let created = engine
+        .generate_migration(
+            GenerateTarget::Migrations,
+            directory.join("migrations"),
+            "define test table",
+            NewMigrationOptions {
+                down: true,
+                header: false,
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        created.path,
+        directory.join("migrations/0002_define_test_table.surql")
+    );
+    let sql = std::fs::read_to_string(&created.path).unwrap();
+    assert!(sql.contains("DEFINE FIELD OVERWRITE string ON test"));
+    assert!(engine.verify_schema_equivalence().await.unwrap().is_empty());
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+fn repeatable_engine(greeting: &str) -> MigrationEngine<Db> {
+    MigrationEngine::new(
+        MemorySource::default()
+            .file(
+                "0001_add_number.surql",
+                "DEFINE FIELD number ON TABLE test TYPE int;",
+            )
+            .file(
+                "R_functions.surql",
+                format!("DEFINE FUNCTION OVERWRITE fn::greet() {{ RETURN '{greeting}'; }};"),
+            ),
+        EmbeddedSource::<SchemaFiles>::new(),
+    )
+}
+
+async fn greet(client: &Surreal<Db>) -> String {
+    let greeting: Option<String> = client
+        .query("RETURN fn::greet();")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    greeting.unwrap()
+}
+
+#[tokio::test]
+async fn reruns_changed_repeatable_migrations() {
+    let client = client().await;
+
+    repeatable_engine("hello").run(&client).await.unwrap();
+    assert_eq!(greet(&client).await, "hello");
+    assert!(repeatable_engine("hello")
+        .plan(&client)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let engine = repeatable_engine("hi");
+    let plan = engine
+        .migrate_to(&client, MigrationTarget::Steps(0))
+        .await
+        .unwrap();
+    assert_eq!(plan.action, PlanAction::UpToDate);
+    assert_eq!(greet(&client).await, "hello");
+    let plan = engine.plan(&client).await.unwrap();
+    assert_eq!(plan.action, PlanAction::ApplyMigrations);
+    assert_eq!(plan.files.len(), 1);
+    assert_eq!(plan.files[0].kind, MigrationKind::Repeatable);
+    engine.run(&client).await.unwrap();
 
-    surrealdb_migration_engine::run::<MigrationFiles,SchemaFiles>(&client).await.unwrap();
-}  
+    assert_eq!(greet(&client).await, "hi");
+    let rows = migration_rows(&client).await;
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].number, REPEATABLE_NUMBER);
+    assert_eq!(rows[0].file_name, "R_functions.surql");
+    let status = engine.status(&client).await.unwrap();
+    assert_eq!(status[1].file_name, "R_functions.surql");
+    assert!(matches!(status[1].state, MigrationState::Applied { .. }));
+}